@@ -1,7 +1,10 @@
 //! https://openauth.baidu.com/doc/doc.html
 use crate::error::Result;
+use crate::secret::Secret;
+use crate::state::StateData;
 use crate::{
-    auth_server_builder, AuthAction, AuthConfig, AuthUrlProvider, AuthUser, GenericAuthAction,
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Gender, GenericAuthAction,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -11,34 +14,53 @@ use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
 }
 
 auth_server_builder!();
 
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://openapi.baidu.com/oauth/2.0/authorize".to_string(),
+            access_token_url: "https://openapi.baidu.com/oauth/2.0/token".to_string(),
+            user_info_url: "https://openapi.baidu.com/rest/2.0/passport/users/getInfo".to_string(),
+        }
+    }
+}
+
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
     type TokenRequest = GetTokenRequest;
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://openapi.baidu.com/oauth/2.0/authorize?response_type=CODE&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://openapi.baidu.com/oauth/2.0/token?grant_type=authorization_code&{query}"
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String> {
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!(
-            "https://openapi.baidu.com/rest/2.0/passport/users/getInfo?{query}"
-        ))
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
@@ -51,41 +73,66 @@ impl AuthAction for AuthorizationServer {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
         let AuthConfig {
             client_id,
-            client_secret,
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let access_token_url = self.access_token_url(GetTokenRequest {
             client_id: client_id.to_string(),
-            client_secret: client_secret.clone().expect("client_secret is empty"),
+            client_secret: self.config.client_secret()?,
             code: callback.code,
             redirect_uri: redirect_uri.to_string(),
         })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
             access_token: token.access_token,
             get_unionid: Some(1),
         })?;
-        Ok(reqwest::get(user_info_url).await?.json().await?)
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let AuthConfig { client_id, .. } = &self.config;
+        let refresh_token_url = self.refresh_token_url(RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            refresh_token: refresh_token.into(),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(refresh_token_url)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "baidu";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: Some(state.into()),
+            state: Some(state),
             scope: scope.clone().unwrap_or_default(),
             ..Default::default()
         })
@@ -93,15 +140,40 @@ impl GenericAuthAction for AuthorizationServer {
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    fn refresh_token_url(&self, request: RefreshTokenRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.access_token_url))
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
         let user = self.get_user_info(token.clone()).await?;
         Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
             user_id: user.openid,
-            name: user.username.unwrap_or_default(),
-            access_token: token.access_token,
-            refresh_token: token.refresh_token,
-            expires_in: token.expires_in,
+            name: user.username.clone().unwrap_or_default(),
+            username: user.username,
+            avatar: utils::get_str(&user.extra, "portrait")
+                .map(|portrait| format!("https://himg.bdimg.com/sys/portrait/item/{portrait}")),
+            gender: match utils::get_str(&user.extra, "sex").as_deref() {
+                Some("1") => Some(Gender::Male),
+                Some("0") => Some(Gender::Female),
+                _ => None,
+            },
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
 }
@@ -149,38 +221,49 @@ pub enum DisplayStyle {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthCallback {
     code: String,
-    state: String,
+    state: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct GetTokenRequest {
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     code: String,
     redirect_uri: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenResponse {
-    pub access_token: String,
+    pub access_token: Secret,
     pub expires_in: i64,
-    pub refresh_token: String,
+    pub refresh_token: Secret,
     pub scope: String,
-    pub session_key: String,
-    pub session_secret: String,
+    pub session_key: Secret,
+    pub session_secret: Secret,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            refresh_token: Some(token.refresh_token),
+            scopes: utils::split_scopes(&token.scope),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshTokenRequest {
     grant_type: String,
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     refresh_token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {
-    access_token: String,
+    access_token: Secret,
     get_unionid: Option<i8>,
 }
 
@@ -191,3 +274,24 @@ pub struct UserInfoResponse {
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// 授权接口失败时返回error字段，OpenAPI失败时返回error_code字段
+///
+/// https://openauth.baidu.com/doc/appendix.html
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+    error_code: Option<i64>,
+    error_msg: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.error_code) {
+            (Some(error), _) => Some((error, self.error_description.unwrap_or_default())),
+            (None, Some(code)) => Some((code.to_string(), self.error_msg.unwrap_or_default())),
+            (None, None) => None,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! 平台级的调用凭证，如飞书的app_access_token，
+//! 这类凭证有获取频率限制，需要缓存到过期前，在多次登录请求之间共享
+use crate::error::Result;
+use crate::secret::Secret;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// 提前刷新的时间，避免使用时刚好过期。有效期较短时最多提前一半的有效期
+const EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+
+/// 凭证缓存，clone后共享同一份缓存。
+/// 每次请求都重新build`AuthorizationServer`时，通过平台的`Options`传入同一个缓存。
+/// 凭证按获取凭证的url和client_id分别缓存，不同应用可以共享同一个缓存
+#[derive(Clone, Default)]
+pub struct AccessTokenCache {
+    inner: Arc<Mutex<HashMap<CacheKey, CacheEntry>>>,
+}
+
+/// (获取凭证的url, client_id)
+type CacheKey = (String, String);
+
+type CacheEntry = Arc<Mutex<Option<CachedToken>>>;
+
+struct CachedToken {
+    token: Secret,
+    expires_at: Instant,
+}
+
+impl AccessTokenCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 缓存有效时直接返回，否则调用`fetch`获取凭证和有效期(秒)。
+    /// 获取期间持有该应用的锁，并发的请求只会获取一次
+    pub(crate) async fn get_or_fetch<F, Fut>(
+        &self,
+        token_url: &str,
+        client_id: &str,
+        fetch: F,
+    ) -> Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(String, i64)>>,
+    {
+        let entry = self.entry(token_url, client_id).await;
+        let mut cached = entry.lock().await;
+        if let Some(cached) = cached.as_ref().filter(|c| Instant::now() < c.expires_at) {
+            return Ok(cached.token.secret().to_string());
+        }
+        let (token, expires_in) = fetch().await?;
+        let ttl = Duration::from_secs(u64::try_from(expires_in).unwrap_or_default());
+        // 平台返回的有效期过大导致溢出时不缓存
+        *cached = Instant::now()
+            .checked_add(ttl - EXPIRY_MARGIN.min(ttl / 2))
+            .map(|expires_at| CachedToken {
+                token: Secret::new(token.clone()),
+                expires_at,
+            });
+        Ok(token)
+    }
+
+    /// 平台返回凭证无效时清除缓存，下次使用时重新获取
+    pub(crate) async fn invalidate(&self, token_url: &str, client_id: &str) {
+        *self.entry(token_url, client_id).await.lock().await = None;
+    }
+
+    async fn entry(&self, token_url: &str, client_id: &str) -> CacheEntry {
+        self.inner
+            .lock()
+            .await
+            .entry((token_url.to_string(), client_id.to_string()))
+            .or_default()
+            .clone()
+    }
+}
+
+impl fmt::Debug for AccessTokenCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AccessTokenCache([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cache_keyed_by_app() {
+        let cache = AccessTokenCache::new();
+        let fetch = |token: &'static str| move || async move { Ok((token.to_string(), 7200)) };
+        let a = cache
+            .get_or_fetch("url", "app-a", fetch("a"))
+            .await
+            .unwrap();
+        let b = cache
+            .get_or_fetch("url", "app-b", fetch("b"))
+            .await
+            .unwrap();
+        assert_eq!((a.as_str(), b.as_str()), ("a", "b"));
+        let a = cache
+            .get_or_fetch("url", "app-a", fetch("c"))
+            .await
+            .unwrap();
+        assert_eq!(a, "a");
+
+        cache.invalidate("url", "app-a").await;
+        let a = cache
+            .get_or_fetch("url", "app-a", fetch("c"))
+            .await
+            .unwrap();
+        let b = cache
+            .get_or_fetch("url", "app-b", fetch("c"))
+            .await
+            .unwrap();
+        assert_eq!((a.as_str(), b.as_str()), ("c", "b"));
+    }
+
+    /// 有效期不超过提前刷新的时间时仍然缓存一半的有效期
+    #[tokio::test]
+    async fn short_lived_token_cached() {
+        let cache = AccessTokenCache::new();
+        let fetch = |token: &'static str| move || async move { Ok((token.to_string(), 120)) };
+        cache.get_or_fetch("url", "app", fetch("a")).await.unwrap();
+        let token = cache.get_or_fetch("url", "app", fetch("b")).await.unwrap();
+        assert_eq!(token, "a");
+    }
+
+    #[tokio::test]
+    async fn overflowing_expires_in() {
+        let cache = AccessTokenCache::new();
+        let fetch = |token: &'static str| move || async move { Ok((token.to_string(), i64::MAX)) };
+        cache.get_or_fetch("url", "app", fetch("a")).await.unwrap();
+        let token = cache.get_or_fetch("url", "app", fetch("b")).await.unwrap();
+        assert_eq!(token, "b");
+    }
+}
@@ -0,0 +1,263 @@
+//! https://open.dingtalk.com/document/orgapp/tutorial-obtaining-user-personal-information
+//! https://open.dingtalk.com/document/orgapp/obtain-user-token
+//!
+//! 钉钉新版登录，获取accessToken和用户信息的接口使用JSON请求体和`x-acs-dingtalk-access-token`请求头。
+//! `user_id`为unionId，用户选择的企业在`tenant_id`中
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+}
+
+auth_server_builder!();
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://login.dingtalk.com/oauth2/auth".to_string(),
+            access_token_url: "https://api.dingtalk.com/v1.0/oauth2/userAccessToken".to_string(),
+            user_info_url: "https://api.dingtalk.com/v1.0/contact/users/me".to_string(),
+        }
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    /// `prompt=consent`为必填参数
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?response_type=code&prompt=consent&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 获取accessToken需要以POST JSON提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = AuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let code = callback
+            .auth_code
+            .or(callback.code)
+            .ok_or(AuthError::MissingCode)?;
+        let request = GetTokenRequest {
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            code: Some(code),
+            refresh_token: None,
+            grant_type: "authorization_code".to_string(),
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .config
+            .http_client
+            .post(access_token_url)
+            .json(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
+            .get(user_info_url)
+            .header("x-acs-dingtalk-access-token", token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let request = GetTokenRequest {
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            code: None,
+            refresh_token: Some(refresh_token.into()),
+            grant_type: "refresh_token".to_string(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .json(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "dingtalk";
+
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            state,
+            scope: scope
+                .clone()
+                .unwrap_or_else(|| vec!["openid".into(), "corpid".into()]),
+        })
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.union_id.clone(),
+            name: user.nick,
+            email: utils::get_str(&user.extra, "email"),
+            avatar: utils::get_str(&user.extra, "avatarUrl"),
+            tenant_id: token.corp_id.clone(),
+            union_id: Some(user.union_id),
+            token: Some(token.into()),
+            extra: user.extra,
+            ..Default::default()
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    client_id: String,
+    redirect_uri: String,
+    /// `openid`只获取用户信息，加上`corpid`时用户需要选择企业
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+}
+
+/// 回调参数为authCode，新版文档中同时带有code，两者都没有时返回[`AuthError::MissingCode`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCallback {
+    #[serde(rename = "authCode")]
+    auth_code: Option<String>,
+    code: Option<String>,
+    state: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTokenRequest {
+    client_id: String,
+    client_secret: Secret,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    grant_type: String,
+}
+
+/// https://open.dingtalk.com/document/orgapp/obtain-user-token
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub refresh_token: Secret,
+    pub expire_in: i64,
+    /// scope包含`corpid`时返回用户选择的企业
+    pub corp_id: Option<String>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            refresh_token: Some(token.refresh_token),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expire_in)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {}
+
+/// https://open.dingtalk.com/document/orgapp/dingtalk-retrieve-user-information
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfoResponse {
+    pub union_id: String,
+    pub nick: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// 失败时返回4xx状态码和`{"code":"...","message":"...","requestid":"..."}`
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    code: Option<String>,
+    message: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        self.code
+            .map(|code| (code, self.message.unwrap_or_default()))
+    }
+}
@@ -13,6 +13,114 @@ pub enum AuthError {
 
     #[error(transparent)]
     ReqwestErr(#[from] reqwest::Error),
+
+    #[error("{0} is not supported by this provider")]
+    Unsupported(&'static str),
+
+    #[error("pkce code_verifier is required")]
+    MissingCodeVerifier,
+
+    #[error("authorization code is missing in callback")]
+    MissingCode,
+
+    #[error("invalid state: {0}")]
+    InvalidState(StateError),
+
+    #[error("invalid id_token: {0}")]
+    InvalidIdToken(#[from] IdTokenError),
+
+    #[error("invalid user data: {0}")]
+    InvalidUserData(#[from] UserDataError),
+
+    #[error("invalid private key: {0}")]
+    InvalidPrivateKey(jsonwebtoken::errors::Error),
+
+    #[error("{0} user is not a member of the organization")]
+    NotMember(&'static str),
+
+    #[error("provider {0} is not registered")]
+    UnknownProvider(String),
+
+    #[error("{0} is required")]
+    MissingConfig(&'static str),
+
+    #[error("{0} is out of range")]
+    ConfigOutOfRange(&'static str),
+
+    #[error("invalid {field}: {source}")]
+    InvalidConfig {
+        field: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("invalid response from {provider}: {message}")]
+    InvalidResponse {
+        provider: &'static str,
+        message: String,
+    },
+
+    #[error("{provider} responded with error {code}: {message} (http status {http_status})")]
+    Provider {
+        provider: &'static str,
+        code: String,
+        message: String,
+        http_status: u16,
+    },
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    #[error("state is missing in callback")]
+    Missing,
+
+    #[error("state is unknown")]
+    Unknown,
+
+    #[error("state is expired")]
+    Expired,
+
+    #[error("state has already been used")]
+    Reused,
+
+    #[error("state does not match the browser session")]
+    Mismatch,
+}
+
+#[derive(Debug, Error)]
+pub enum IdTokenError {
+    #[error("id_token is missing in token response")]
+    Missing,
+
+    #[error("signing key {0:?} is not found in jwks")]
+    UnknownKey(Option<String>),
+
+    #[error("signing algorithm {0:?} is not allowed")]
+    UnsupportedAlgorithm(jsonwebtoken::Algorithm),
+
+    #[error("claim {0} does not match")]
+    ClaimMismatch(&'static str),
+
+    #[error(transparent)]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+}
+
+/// 微信小程序开放数据的签名校验和解密错误
+#[derive(Debug, Error)]
+pub enum UserDataError {
+    #[error("signature does not match raw data")]
+    SignatureMismatch,
+
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("session_key or iv has invalid length")]
+    InvalidKey,
+
+    #[error("decryption failed")]
+    Decrypt,
+
+    #[error("watermark appid {0:?} does not match")]
+    WatermarkMismatch(String),
 }
 
 pub type Result<T> = std::result::Result<T, AuthError>;
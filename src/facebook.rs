@@ -1,7 +1,9 @@
 //! https://developers.facebook.com/docs/facebook-login/guides/advanced/manual-flow
+use crate::secret::Secret;
+use crate::state::StateData;
 use crate::{
-    auth_server_builder, error::Result, AuthAction, AuthConfig, AuthUrlProvider, AuthUser,
-    GenericAuthAction,
+    auth_server_builder, error::Result, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider,
+    AuthUser, GenericAuthAction,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -11,10 +13,39 @@ use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
 }
 
 auth_server_builder!();
 
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub revoke_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self::version("v21.0")
+    }
+}
+
+impl Endpoints {
+    /// 指定Graph API版本，如`v22.0`
+    pub fn version<S: AsRef<str>>(version: S) -> Self {
+        let version = version.as_ref();
+        Self {
+            authorize_url: format!("https://www.facebook.com/{version}/dialog/oauth"),
+            access_token_url: format!("https://graph.facebook.com/{version}/oauth/access_token"),
+            user_info_url: format!("https://graph.facebook.com/{version}/me"),
+            revoke_url: format!("https://graph.facebook.com/{version}/me/permissions"),
+        }
+    }
+}
+
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
 
@@ -22,24 +53,26 @@ impl AuthUrlProvider for AuthorizationServer {
 
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://www.facebook.com/v21.0/dialog/oauth?response_type=token&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://graph.facebook.com/v21.0/oauth/access_token?grant_type=authorization_code&{query}"
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
     /// https://developers.facebook.com/docs/graph-api/overview#me
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String> {
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!("https://graph.facebook.com/me?{query}"))
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
@@ -52,40 +85,48 @@ impl AuthAction for AuthorizationServer {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
         let AuthConfig {
             client_id,
-            client_secret,
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let access_token_url = self.access_token_url(GetTokenRequest {
             client_id: client_id.to_string(),
-            client_secret: client_secret.clone().expect("client_secret is empty"),
+            client_secret: self.config.client_secret()?,
             code: callback.code,
             redirect_uri: redirect_uri.to_string(),
         })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
             access_token: token.access_token,
+            fields: ["id", "name", "email", "picture", "locale", "link"]
+                .map(|s| s.to_string())
+                .to_vec(),
         })?;
-        Ok(reqwest::get(user_info_url).await?.json().await?)
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "facebook";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: Some(state.into()),
+            state: Some(state),
             scope: scope.clone().unwrap_or_default(),
             ..Default::default()
         })
@@ -93,15 +134,68 @@ impl GenericAuthAction for AuthorizationServer {
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    /// 删除用户授予应用的所有权限
+    ///
+    /// https://developers.facebook.com/docs/facebook-login/guides/permissions/request-revoke#revoke-permissions
+    async fn revoke<S: Into<String> + Send>(&self, access_token: S) -> Result<()> {
+        let response = self
+            .config
+            .http_client
+            .delete(&self.endpoints.revoke_url)
+            .query(&[("access_token", access_token.into())])
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    /// Facebook没有refresh_token，`refresh`返回[`crate::error::AuthError::Unsupported`]。
+    /// 可以用当前未过期的短期accessToken换取一个约60天有效的长期accessToken
+    ///
+    /// https://developers.facebook.com/docs/facebook-login/guides/access-tokens/get-long-lived
+    pub async fn exchange_long_lived_token<S: Into<String>>(
+        &self,
+        access_token: S,
+    ) -> Result<TokenResponse> {
+        let query = serde_urlencoded::to_string(ExchangeTokenRequest {
+            grant_type: "fb_exchange_token".to_string(),
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            fb_exchange_token: access_token.into(),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(format!("{}?{query}", self.endpoints.access_token_url))
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
         let user = self.get_user_info(token.clone()).await?;
         Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
             user_id: user.id,
             name: user.name,
-            access_token: token.access_token,
-            refresh_token: token.token_type,
-            expires_in: token.expires_in,
+            email: utils::get_str(&user.extra, "email"),
+            avatar: user
+                .extra
+                .get("picture")
+                .and_then(|picture| picture.pointer("/data/url"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            locale: utils::get_str(&user.extra, "locale"),
+            profile_url: utils::get_str(&user.extra, "link"),
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
 }
@@ -120,34 +214,49 @@ pub struct AuthRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthCallback {
     code: String,
-    state: String,
+    state: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetTokenRequest {
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     code: String,
     redirect_uri: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct RefreshTokenRequest {
+pub struct ExchangeTokenRequest {
     grant_type: String,
-    appid: String,
-    refresh_token: String,
+    client_id: String,
+    client_secret: Secret,
+    fb_exchange_token: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenResponse {
-    pub access_token: String,
+    pub access_token: Secret,
     pub expires_in: i64,
     pub token_type: String,
 }
 
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in)
+    }
+}
+
+#[serde_as]
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {
-    access_token: String,
+    access_token: Secret,
+    /// 默认只返回id和name，其他字段需要显式指定
+    #[serde_as(as = "StringWithSeparator::<CommaSeparator, String>")]
+    fields: Vec<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -157,3 +266,27 @@ pub struct UserInfoResponse {
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// https://developers.facebook.com/docs/graph-api/guides/error-handling
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<GraphError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphError {
+    message: String,
+    code: i64,
+    error_subcode: Option<i64>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        let error = self.error?;
+        let code = match error.error_subcode {
+            Some(subcode) => format!("{}/{subcode}", error.code),
+            None => error.code.to_string(),
+        };
+        Some((code, error.message))
+    }
+}
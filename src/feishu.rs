@@ -0,0 +1,381 @@
+//! https://open.feishu.cn/document/common-capabilities/sso/web-application-sso/web-app-overview
+//! https://open.feishu.cn/document/server-docs/authentication-management/access-token/app_access_token_internal
+//!
+//! 飞书和Lark的网页应用登录，Lark使用[`Endpoints::lark`]。
+//! 获取user_access_token前需要先用app_id(client_id)和app_secret(client_secret)获取app_access_token，
+//! app_access_token缓存在[`Options::app_access_token`]中，过期前不会重新获取。
+//! 只支持企业自建应用，商店应用需要app_ticket。
+//!
+//! [`AuthUser::user_id`]和[`AuthUser::open_id`]为应用内唯一的open_id，飞书租户内的user_id保存在[`AuthUser::tenant_user_id`]，
+//! 需要`contact:user.employee_id:readonly`权限才会返回
+use crate::credential::AccessTokenCache;
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+use std::future::Future;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+}
+
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为飞书的官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub app_access_token_url: String,
+    pub access_token_url: String,
+    pub refresh_token_url: String,
+    pub user_info_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self::with_base_url("https://open.feishu.cn")
+    }
+}
+
+impl Endpoints {
+    /// Lark(飞书国际版)的接口地址
+    pub fn lark() -> Self {
+        Self::with_base_url("https://open.larksuite.com")
+    }
+
+    fn with_base_url(base_url: &str) -> Self {
+        Self {
+            authorize_url: format!("{base_url}/open-apis/authen/v1/authorize"),
+            app_access_token_url: format!("{base_url}/open-apis/auth/v3/app_access_token/internal"),
+            access_token_url: format!("{base_url}/open-apis/authen/v1/oidc/access_token"),
+            refresh_token_url: format!("{base_url}/open-apis/authen/v1/oidc/refresh_access_token"),
+            user_info_url: format!("{base_url}/open-apis/authen/v1/user_info"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// app_access_token的缓存，多个`AuthorizationServer`可以共享同一个缓存，不同应用分别缓存
+    pub app_access_token: AccessTokenCache,
+}
+
+/// app_access_token无效或过期的错误码
+///
+/// https://open.feishu.cn/document/server-docs/api-call-guide/generic-error-code
+const INVALID_APP_ACCESS_TOKEN: [&str; 2] = ["99991663", "99991664"];
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.authorize_url))
+    }
+
+    /// 获取accessToken需要以POST JSON提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = AuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code: callback.code,
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        self.with_app_access_token(|app_access_token| {
+            self.post(&access_token_url, &request, app_access_token)
+        })
+        .await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
+            .get(user_info_url)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        let response: DataResponse<UserInfoResponse> =
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+        Ok(response.data)
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.into(),
+        };
+        self.with_app_access_token(|app_access_token| {
+            self.post(
+                &self.endpoints.refresh_token_url,
+                &request,
+                app_access_token,
+            )
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "feishu";
+
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            app_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            state,
+            scope: scope.clone().unwrap_or_default(),
+        })
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+        })
+    }
+
+    /// 获取缓存的app_access_token，过期时重新获取
+    pub async fn app_access_token(&self) -> Result<String> {
+        self.options
+            .app_access_token
+            .get_or_fetch(
+                &self.endpoints.app_access_token_url,
+                &self.config.client_id,
+                || async {
+                    let request = AppAccessTokenRequest {
+                        app_id: self.config.client_id.to_string(),
+                        app_secret: self.config.client_secret()?,
+                    };
+                    let response = self
+                        .config
+                        .http_client
+                        .post(&self.endpoints.app_access_token_url)
+                        .json(&request)
+                        .send()
+                        .await?;
+                    let token: AppAccessTokenResponse =
+                        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+                    Ok((token.app_access_token.secret().to_string(), token.expire))
+                },
+            )
+            .await
+    }
+
+    /// app_access_token被提前作废时清除缓存并重试一次
+    async fn with_app_access_token<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let app_access_token = self.app_access_token().await?;
+        match request(app_access_token).await {
+            Err(AuthError::Provider { code, .. })
+                if INVALID_APP_ACCESS_TOKEN.contains(&code.as_str()) =>
+            {
+                self.options
+                    .app_access_token
+                    .invalidate(&self.endpoints.app_access_token_url, &self.config.client_id)
+                    .await;
+                request(self.app_access_token().await?).await
+            }
+            result => result,
+        }
+    }
+
+    async fn post<T: Serialize, R: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &T,
+        app_access_token: String,
+    ) -> Result<R> {
+        let response = self
+            .config
+            .http_client
+            .post(url)
+            .bearer_auth(app_access_token)
+            .json(body)
+            .send()
+            .await?;
+        let response: DataResponse<R> =
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+        Ok(response.data)
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.open_id.clone(),
+            open_id: Some(user.open_id),
+            name: user.name,
+            email: utils::get_str(&user.extra, "email")
+                .or_else(|| utils::get_str(&user.extra, "enterprise_email")),
+            avatar: utils::get_str(&user.extra, "avatar_url"),
+            union_id: utils::get_str(&user.extra, "union_id"),
+            tenant_id: utils::get_str(&user.extra, "tenant_key"),
+            tenant_user_id: utils::get_str(&user.extra, "user_id"),
+            token: Some(token.into()),
+            extra: user.extra,
+            ..Default::default()
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    app_id: String,
+    redirect_uri: String,
+    /// 为空时只获取基本信息，需要refresh_token时加上`offline_access`
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scope: Vec<String>,
+    state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCallback {
+    code: String,
+    state: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AppAccessTokenRequest {
+    app_id: String,
+    app_secret: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+struct AppAccessTokenResponse {
+    app_access_token: Secret,
+    /// 有效期(秒)
+    expire: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    refresh_token: String,
+}
+
+/// 接口返回`{"code":0,"msg":"success","data":{...}}`
+#[derive(Debug, Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+/// https://open.feishu.cn/document/common-capabilities/sso/api/get-user-access-token
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: String,
+    pub expires_in: i64,
+    /// scope包含`offline_access`时返回
+    pub refresh_token: Option<Secret>,
+    pub refresh_expires_in: Option<i64>,
+    pub scope: Option<String>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope.unwrap_or_default()),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in)
+        .with_refresh_expires_in(token.refresh_expires_in.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {}
+
+/// https://open.feishu.cn/document/common-capabilities/sso/api/get-user-info
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub open_id: String,
+    pub name: String,
+    /// 包括union_id、tenant_key和租户内的user_id
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// 错误时code不为0，部分错误的http状态码为200
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    code: Option<i64>,
+    msg: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match self.code {
+            Some(0) | None => None,
+            Some(code) => Some((code.to_string(), self.msg.unwrap_or_default())),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! https://gitee.com/api/v5/oauth_doc
+use crate::error::Result;
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+}
+
+auth_server_builder!();
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub emails_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://gitee.com/oauth/authorize".to_string(),
+            access_token_url: "https://gitee.com/oauth/token".to_string(),
+            user_info_url: "https://gitee.com/api/v5/user".to_string(),
+            emails_url: "https://gitee.com/api/v5/emails".to_string(),
+        }
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 获取accessToken需要以POST表单提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = AuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            code: callback.code,
+            redirect_uri: redirect_uri.to_string(),
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .config
+            .http_client
+            .post(access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
+            access_token: token.access_token,
+        })?;
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.into(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "gitee";
+
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            state,
+            scope: scope
+                .clone()
+                .unwrap_or_else(|| vec!["user_info".into(), "emails".into()]),
+        })
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    /// 用户的邮箱列表，授权时scope需要包含`emails`
+    ///
+    /// https://gitee.com/api/v5/swagger#/getV5Emails
+    pub async fn emails<S: Into<String>>(&self, access_token: S) -> Result<Vec<Email>> {
+        let query = serde_urlencoded::to_string(GetUserInfoRequest {
+            access_token: Secret::new(access_token),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(format!("{}?{query}", self.endpoints.emails_url))
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 用户信息中的email为公开邮箱，未公开时从邮箱列表中取主邮箱
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        let mut email = utils::get_str(&user.extra, "email");
+        let mut email_verified = None;
+        if email.is_none()
+            && utils::split_scopes(&token.scope)
+                .iter()
+                .any(|s| s == "emails")
+        {
+            let primary = self
+                .emails(token.access_token.secret())
+                .await?
+                .into_iter()
+                .find(|email| email.scope.iter().any(|scope| scope == "primary"));
+            if let Some(primary) = primary {
+                email_verified = Some(primary.state == "confirmed");
+                email = Some(primary.email);
+            }
+        }
+        let username = utils::get_str(&user.extra, "login");
+        Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.id.to_string(),
+            name: user.name.or_else(|| username.clone()).unwrap_or_default(),
+            email,
+            email_verified,
+            avatar: utils::get_str(&user.extra, "avatar_url"),
+            profile_url: utils::get_str(&user.extra, "html_url"),
+            username,
+            token: Some(token.into()),
+            extra: user.extra,
+            ..Default::default()
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    client_id: String,
+    redirect_uri: String,
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCallback {
+    code: String,
+    state: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    client_id: String,
+    client_secret: Secret,
+    code: String,
+    redirect_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    refresh_token: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: Secret,
+    pub scope: String,
+    pub created_at: Option<i64>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: Some(token.refresh_token),
+            scopes: utils::split_scopes(&token.scope),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {
+    access_token: Secret,
+}
+
+/// https://gitee.com/api/v5/swagger#/getV5User
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub id: i64,
+    /// 用户未设置时为null
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Email {
+    pub email: String,
+    /// 已验证时为`confirmed`
+    pub state: String,
+    /// 如`primary`、`committed`
+    #[serde(default)]
+    pub scope: Vec<String>,
+}
+
+/// 获取accessToken失败时返回error字段，API请求失败时只有message字段
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+    message: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.message) {
+            (Some(error), _) => Some((error, self.error_description.unwrap_or_default())),
+            (None, Some(message)) => Some((String::new(), message)),
+            (None, None) => None,
+        }
+    }
+}
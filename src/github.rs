@@ -1,7 +1,10 @@
 //! https://docs.github.com/zh/apps/oauth-apps/building-oauth-apps/authorizing-oauth-apps
-use crate::error::Result;
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
 use crate::{
-    auth_server_builder, AuthAction, AuthConfig, AuthUrlProvider, AuthUser, GenericAuthAction,
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    GenericAuthAction,
 };
 use async_trait::async_trait;
 use reqwest::header::ACCEPT;
@@ -12,29 +15,83 @@ use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
 }
 
-auth_server_builder!();
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    /// 撤销授权的接口为`{applications_url}/{client_id}/grant`
+    pub applications_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://github.com/login/oauth/authorize".to_string(),
+            access_token_url: "https://github.com/login/oauth/access_token".to_string(),
+            user_info_url: "https://api.github.com/user".to_string(),
+            applications_url: "https://api.github.com/applications".to_string(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// GitHub Enterprise Server的接口地址，`base_url`如`https://github.example.com`
+    pub fn enterprise<S: AsRef<str>>(base_url: S) -> Self {
+        let base_url = base_url.as_ref().trim_end_matches('/');
+        Self {
+            authorize_url: format!("{base_url}/login/oauth/authorize"),
+            access_token_url: format!("{base_url}/login/oauth/access_token"),
+            user_info_url: format!("{base_url}/api/v3/user"),
+            applications_url: format!("{base_url}/api/v3/applications"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 应用是开启了token过期的GitHub App，只有这类应用会返回refreshToken，
+    /// 未开启时`refresh`返回[`AuthError::Unsupported`]
+    pub expiring_tokens: bool,
+}
+
+impl AuthConfigBuilder {
+    /// 设置应用是否开启了token过期
+    pub fn expiring_tokens(mut self, expiring_tokens: bool) -> Self {
+        self.options
+            .get_or_insert_with(Options::default)
+            .expiring_tokens = expiring_tokens;
+        self
+    }
+}
 
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
     type TokenRequest = GetTokenRequest;
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!("https://github.com/login/oauth/authorize?{query}"))
+        Ok(format!("{}?{query}", self.endpoints.authorize_url))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://github.com/login/oauth/access_token?token_type=bearer&{query}"
+            "{}?token_type=bearer&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
-    fn user_info_url(_request: Self::UserInfoRequest) -> Result<String> {
-        Ok(format!("https://api.github.com/user"))
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
     }
 }
 
@@ -47,69 +104,146 @@ impl AuthAction for AuthorizationServer {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
         let AuthConfig {
             client_id,
-            client_secret,
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let access_token_url = self.access_token_url(GetTokenRequest {
             client_id: client_id.to_string(),
-            client_secret: client_secret.clone().expect("client_secret is empty"),
+            client_secret: self.config.client_secret()?,
             code: callback.code,
             redirect_uri: redirect_uri.clone(),
         })?;
-        Ok(reqwest::Client::default()
+        let response = self
+            .config
+            .http_client
             .get(access_token_url)
             .header(ACCEPT, "application/json")
             .send()
-            .await?
-            .json()
-            .await?)
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {})?;
-        Ok(reqwest::Client::default()
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
             .get(user_info_url)
-            .bearer_auth(token.access_token)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 只有开启了token过期的GitHub App会返回refreshToken，未设置[`Options::expiring_tokens`]时返回[`AuthError::Unsupported`]
+    ///
+    /// https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/refreshing-user-access-tokens
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        if !self.options.expiring_tokens {
+            return Err(AuthError::Unsupported("refresh_token"));
+        }
+        let request = RefreshTokenRequest {
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.into(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .header(ACCEPT, "application/json")
+            .form(&request)
             .send()
-            .await?
-            .json()
-            .await?)
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "github";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: state.into(),
+            state,
             scope: scope
                 .clone()
-                .or_else(|| Some(vec!["read:user".into(), "user:email".into()]))
-                .expect("scope is empty"),
+                .unwrap_or_else(|| vec!["read:user".into(), "user:email".into()]),
             ..Default::default()
         })
     }
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+
+    /// 删除用户对应用的授权，该用户的所有token都会失效
+    ///
+    /// https://docs.github.com/en/rest/apps/oauth-applications#delete-an-app-authorization
+    async fn revoke<S: Into<String> + Send>(&self, access_token: S) -> Result<()> {
+        let AuthConfig { client_id, .. } = &self.config;
+        let revoke_url = format!("{}/{client_id}/grant", self.endpoints.applications_url);
+        let response = self
+            .config
+            .http_client
+            .delete(revoke_url)
+            .basic_auth(client_id, Some(self.config.client_secret()?.secret()))
+            .header(ACCEPT, "application/vnd.github+json")
+            .json(&RevokeRequest {
+                access_token: access_token.into(),
+            })
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+        })
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
         let user = self.get_user_info(token.clone()).await?;
+        let username = utils::get_str(&user.extra, "login");
         Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
             user_id: user.id.to_string(),
-            name: user.name,
-            access_token: token.access_token,
-            refresh_token: token.token_type,
-            expires_in: i64::MAX,
+            name: user.name.or_else(|| username.clone()).unwrap_or_default(),
+            email: utils::get_str(&user.extra, "email"),
+            avatar: utils::get_str(&user.extra, "avatar_url"),
+            location: utils::get_str(&user.extra, "location"),
+            profile_url: utils::get_str(&user.extra, "html_url"),
+            username,
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
 }
@@ -130,32 +264,83 @@ pub struct AuthRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthCallback {
     code: String,
-    state: String,
+    state: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct GetTokenRequest {
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     code: String,
     redirect_uri: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    client_id: String,
+    client_secret: Secret,
+    grant_type: String,
+    refresh_token: String,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenResponse {
-    pub access_token: String,
+    pub access_token: Secret,
     pub scope: String,
     pub token_type: String,
+    /// 开启了token过期的GitHub App才会返回以下字段
+    pub expires_in: Option<i64>,
+    pub refresh_token: Option<Secret>,
+    pub refresh_token_expires_in: Option<i64>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+        .with_refresh_expires_in(token.refresh_token_expires_in.unwrap_or_default())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {}
 
+#[derive(Debug, Serialize)]
+pub struct RevokeRequest {
+    access_token: String,
+}
+
 /// https://docs.github.com/en/rest/users/users
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UserInfoResponse {
     pub id: i64,
-    pub name: String,
+    /// 用户未设置时为null
+    pub name: Option<String>,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// 获取accessToken失败时返回200和error字段，API请求失败时只有message字段
+///
+/// https://docs.github.com/en/apps/oauth-apps/maintaining-oauth-apps/troubleshooting-oauth-app-access-token-request-errors
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+    message: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.message) {
+            (Some(error), _) => Some((error, self.error_description.unwrap_or_default())),
+            (None, Some(message)) => Some((String::new(), message)),
+            (None, None) => None,
+        }
+    }
+}
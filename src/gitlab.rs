@@ -0,0 +1,438 @@
+//! https://docs.gitlab.com/api/oauth2/
+//! https://docs.gitlab.com/api/users/#get-the-current-user
+//!
+//! 支持gitlab.com和私有部署的GitLab，私有部署使用[`Endpoints::self_managed`]。
+//! GitLab的accessToken有效期为2小时，需要通过[`GenericAuthAction::refresh`]刷新
+use crate::error::Result;
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeVerifier};
+use crate::secret::Secret;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Authorization, GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+}
+
+auth_server_builder!(public_client);
+
+/// 各接口地址，默认为gitlab.com
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub groups_url: String,
+    pub revoke_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self::self_managed("https://gitlab.com")
+    }
+}
+
+impl Endpoints {
+    /// 私有部署的GitLab，`base_url`如`https://gitlab.example.com`
+    pub fn self_managed<S: AsRef<str>>(base_url: S) -> Self {
+        let base_url = base_url.as_ref().trim_end_matches('/');
+        Self {
+            authorize_url: format!("{base_url}/oauth/authorize"),
+            access_token_url: format!("{base_url}/oauth/token"),
+            user_info_url: format!("{base_url}/api/v4/user"),
+            groups_url: format!("{base_url}/api/v4/groups"),
+            revoke_url: format!("{base_url}/oauth/revoke"),
+        }
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 获取accessToken需要以POST表单提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = PkceAuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret().ok(),
+            code: callback.code,
+            redirect_uri: redirect_uri.to_string(),
+            code_verifier: callback.code_verifier,
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .config
+            .http_client
+            .post(access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
+            .get(user_info_url)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 刷新后旧的refreshToken失效，需要保存新返回的refreshToken
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret().ok(),
+            refresh_token: refresh_token.into(),
+            redirect_uri: redirect_uri.to_string(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "gitlab";
+
+    /// 自动生成PKCE，code_verifier随state一起保存
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        let authorization = self.config.begin_authorization(&state, true, false).await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let (callback, _) = self
+            .config
+            .verify_pkce_callback(&callback.into(), None)
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        state: S,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        let state = state.into();
+        let authorization = self
+            .config
+            .begin_authorization_with_pkce(&state, code_challenge, false)
+            .await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        callback: S,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        let (callback, _) = self
+            .config
+            .verify_pkce_callback(&callback.into(), Some(code_verifier))
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+
+    /// 撤销accessToken或refreshToken
+    async fn revoke<S: Into<String> + Send>(&self, token: S) -> Result<()> {
+        let token = token.into();
+        let client_secret = self.config.client_secret().ok();
+        let request = utils::RevokeTokenRequest {
+            token: &token,
+            token_type_hint: None,
+            client_id: Some(&self.config.client_id),
+            client_secret: client_secret.as_ref().map(Secret::secret),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.revoke_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    /// 用户所属的群组，`min_access_level`为最低的角色，如10为Guest、30为Developer。
+    /// 授权时scope需要包含`read_api`
+    ///
+    /// https://docs.gitlab.com/api/groups/#list-groups
+    pub async fn groups<S: AsRef<str>>(
+        &self,
+        access_token: S,
+        min_access_level: u32,
+    ) -> Result<Vec<Group>> {
+        let mut groups = Vec::new();
+        let mut page = 1;
+        loop {
+            let query = serde_urlencoded::to_string(GetGroupsRequest {
+                min_access_level,
+                page,
+                per_page: 100,
+            })?;
+            let response = self
+                .config
+                .http_client
+                .get(format!("{}?{query}", self.endpoints.groups_url))
+                .bearer_auth(access_token.as_ref())
+                .send()
+                .await?;
+            let next_page = response
+                .headers()
+                .get("x-next-page")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            let mut page_groups: Vec<Group> =
+                utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+            groups.append(&mut page_groups);
+            match next_page {
+                Some(next_page) => page = next_page,
+                None => return Ok(groups),
+            }
+        }
+    }
+
+    fn build_authorize_url(&self, state: String, authorization: Authorization) -> Result<String> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            state,
+            scope: scope.clone().unwrap_or_else(|| vec!["read_user".into()]),
+            code_challenge: authorization.code_challenge(),
+            code_challenge_method: authorization.code_challenge_method(),
+        })
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.id.to_string(),
+            name: user.name,
+            username: Some(user.username),
+            email: utils::get_str(&user.extra, "email")
+                .or_else(|| utils::get_str(&user.extra, "public_email")),
+            avatar: utils::get_str(&user.extra, "avatar_url"),
+            location: utils::get_str(&user.extra, "location"),
+            profile_url: utils::get_str(&user.extra, "web_url"),
+            token: Some(token.into()),
+            extra: user.extra,
+            ..Default::default()
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    client_id: String,
+    redirect_uri: String,
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    client_id: String,
+    /// 非机密应用不需要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+    code: String,
+    redirect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+    refresh_token: String,
+    redirect_uri: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: String,
+    pub expires_in: Option<i64>,
+    pub refresh_token: Option<Secret>,
+    pub scope: Option<String>,
+    pub created_at: Option<i64>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope.unwrap_or_default()),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {}
+
+#[derive(Debug, Serialize)]
+struct GetGroupsRequest {
+    min_access_level: u32,
+    page: u32,
+    per_page: u32,
+}
+
+/// https://docs.gitlab.com/api/users/#get-the-current-user
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub id: i64,
+    pub username: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    /// 包含父群组的完整路径，如`company/team`
+    pub full_path: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// OAuth接口返回`error`和`error_description`，API返回`message`或`error`
+///
+/// https://docs.gitlab.com/api/rest/troubleshooting/
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+    message: Option<Value>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.message) {
+            (Some(error), _) => Some((error, self.error_description.unwrap_or_default())),
+            (None, Some(Value::String(message))) => Some((String::new(), message)),
+            (None, Some(message)) => Some((String::new(), message.to_string())),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn self_managed_endpoints() {
+        let endpoints = Endpoints::self_managed("https://gitlab.example.com/");
+        assert_eq!(
+            endpoints.authorize_url,
+            "https://gitlab.example.com/oauth/authorize"
+        );
+        assert_eq!(
+            endpoints.access_token_url,
+            "https://gitlab.example.com/oauth/token"
+        );
+        assert_eq!(
+            endpoints.user_info_url,
+            "https://gitlab.example.com/api/v4/user"
+        );
+        assert_eq!(
+            endpoints.groups_url,
+            "https://gitlab.example.com/api/v4/groups"
+        );
+        assert_eq!(
+            endpoints.revoke_url,
+            "https://gitlab.example.com/oauth/revoke"
+        );
+        assert_eq!(
+            Endpoints::default(),
+            Endpoints::self_managed("https://gitlab.com")
+        );
+    }
+}
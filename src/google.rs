@@ -0,0 +1,459 @@
+//! https://developers.google.com/identity/protocols/oauth2/web-server
+//! https://developers.google.com/identity/openid-connect/openid-connect
+//!
+//! 会自动生成nonce和PKCE，和state一起保存到StateStore，登录时校验id_token的签名、nonce，
+//! 配置了[`Options::hosted_domain`]时还会校验id_token中的hd
+use crate::error::{IdTokenError, Result};
+use crate::oidc::{self, IdTokenVerifier};
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeVerifier};
+use crate::secret::Secret;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Authorization, GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+    verifier: IdTokenVerifier,
+}
+
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub jwks_url: String,
+    pub revoke_url: String,
+    /// id_token的签发方
+    pub issuer: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            access_token_url: "https://oauth2.googleapis.com/token".to_string(),
+            user_info_url: "https://openidconnect.googleapis.com/v1/userinfo".to_string(),
+            jwks_url: "https://www.googleapis.com/oauth2/v3/certs".to_string(),
+            revoke_url: "https://oauth2.googleapis.com/revoke".to_string(),
+            issuer: "https://accounts.google.com".to_string(),
+        }
+    }
+}
+
+/// https://developers.google.com/identity/protocols/oauth2/web-server#creatingclient
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 授权时传`access_type=offline`，首次授权时会返回refresh_token
+    pub offline_access: bool,
+    /// 空格分隔的`none`、`consent`、`select_account`，
+    /// 已授权过的用户需要`consent`才会再次返回refresh_token
+    pub prompt: Option<String>,
+    /// 只允许该Google Workspace域名的账号登录，`*`表示任意Workspace账号，
+    /// 登录时会校验id_token中的hd，不只是作为授权参数
+    pub hosted_domain: Option<String>,
+    /// 默认的登录账号提示，也可以通过[`AuthorizationServer::authorize_with_login_hint`]按请求指定
+    pub login_hint: Option<String>,
+    /// 增量授权，新的授权包含用户之前授予的scope
+    pub include_granted_scopes: bool,
+    /// 见[`IdTokenVerifier::leeway`]
+    pub leeway: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            offline_access: false,
+            prompt: None,
+            hosted_domain: None,
+            login_hint: None,
+            include_granted_scopes: false,
+            leeway: oidc::DEFAULT_LEEWAY,
+        }
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 获取accessToken需要以POST表单提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = PkceAuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            code: callback.code,
+            redirect_uri: redirect_uri.to_string(),
+            code_verifier: callback.code_verifier,
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .config
+            .http_client
+            .post(access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
+            .get(user_info_url)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 授权时需要开启[`Options::offline_access`]才会返回refresh_token
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            refresh_token: refresh_token.into(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "google";
+
+    /// 自动生成nonce和PKCE，和state一起保存
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        self.authorize_with_hint(state.into(), self.options.login_hint.clone())
+            .await
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), None)
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref(), true).await
+    }
+
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        state: S,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        let state = state.into();
+        let authorization = self
+            .config
+            .begin_authorization_with_pkce(&state, code_challenge, true)
+            .await?;
+        self.build_authorize_url(state, authorization, self.options.login_hint.clone())
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        callback: S,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), Some(code_verifier))
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref(), true).await
+    }
+
+    /// 刷新时返回的id_token不带nonce，没有id_token时从userinfo接口获取用户信息
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token, None, false).await
+    }
+
+    /// 撤销accessToken或refreshToken，撤销refreshToken时对应的accessToken也会失效
+    async fn revoke<S: Into<String> + Send>(&self, token: S) -> Result<()> {
+        let token = token.into();
+        let request = utils::RevokeTokenRequest {
+            token: &token,
+            token_type_hint: None,
+            client_id: None,
+            client_secret: None,
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.revoke_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        let mut verifier =
+            IdTokenVerifier::new(Self::PROVIDER, &endpoints.jwks_url, &config.client_id)
+                .issuer(&endpoints.issuer)
+                .leeway(options.leeway);
+        // Google签发的id_token中iss可能不带https://
+        if let Some(issuer) = endpoints.issuer.strip_prefix("https://") {
+            verifier = verifier.issuer(issuer);
+        }
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+            verifier,
+        })
+    }
+
+    /// 指定本次授权的登录账号提示，如用户输入的邮箱或sub，覆盖[`Options::login_hint`]
+    pub async fn authorize_with_login_hint<S: Into<String>, H: Into<String>>(
+        &self,
+        state: S,
+        login_hint: H,
+    ) -> Result<String> {
+        self.authorize_with_hint(state.into(), Some(login_hint.into()))
+            .await
+    }
+
+    async fn authorize_with_hint(
+        &self,
+        state: String,
+        login_hint: Option<String>,
+    ) -> Result<String> {
+        let authorization = self.config.begin_authorization(&state, true, true).await?;
+        self.build_authorize_url(state, authorization, login_hint)
+    }
+
+    fn build_authorize_url(
+        &self,
+        state: String,
+        authorization: Authorization,
+        login_hint: Option<String>,
+    ) -> Result<String> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let Options {
+            offline_access,
+            prompt,
+            hosted_domain,
+            include_granted_scopes,
+            ..
+        } = &self.options;
+        self.authorize_url(AuthRequest {
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: self.config.openid_scope(),
+            state,
+            code_challenge: authorization.code_challenge(),
+            code_challenge_method: authorization.code_challenge_method(),
+            nonce: authorization.nonce,
+            access_type: offline_access.then(|| "offline".to_string()),
+            prompt: prompt.clone(),
+            hd: hosted_domain.clone(),
+            login_hint,
+            include_granted_scopes: include_granted_scopes.then_some(true),
+        })
+    }
+
+    async fn auth_user(
+        &self,
+        token: TokenResponse,
+        nonce: Option<&str>,
+        id_token_required: bool,
+    ) -> Result<AuthUser> {
+        let (sub, claims) = match &token.id_token {
+            Some(id_token) => {
+                let claims = self
+                    .verifier
+                    .verify(&self.config.http_client, id_token.secret(), nonce)
+                    .await?;
+                (claims.sub, claims.extra)
+            }
+            None if id_token_required => return Err(IdTokenError::Missing.into()),
+            None => {
+                let user = self.get_user_info(token.clone()).await?;
+                (user.sub, user.extra)
+            }
+        };
+        self.check_hosted_domain(&claims)?;
+        Ok(oidc::standard_user(
+            Self::PROVIDER,
+            sub,
+            claims,
+            token.into(),
+        ))
+    }
+
+    /// 授权参数中的hd可以被用户篡改，需要校验Google返回的hd
+    fn check_hosted_domain(&self, claims: &HashMap<String, Value>) -> Result<()> {
+        let Some(hosted_domain) = &self.options.hosted_domain else {
+            return Ok(());
+        };
+        match utils::get_str(claims, "hd") {
+            Some(hd) if hosted_domain == "*" || hd.eq_ignore_ascii_case(hosted_domain) => Ok(()),
+            _ => Err(IdTokenError::ClaimMismatch("hd").into()),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    client_id: String,
+    redirect_uri: String,
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    access_type: Option<String>,
+    prompt: Option<String>,
+    hd: Option<String>,
+    login_hint: Option<String>,
+    include_granted_scopes: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    client_id: String,
+    client_secret: Secret,
+    code: String,
+    redirect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    client_id: String,
+    client_secret: Secret,
+    refresh_token: String,
+}
+
+/// https://developers.google.com/identity/protocols/oauth2/web-server#exchange-authorization-code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: String,
+    pub expires_in: Option<i64>,
+    /// 只有`access_type=offline`的首次授权才会返回
+    pub refresh_token: Option<Secret>,
+    /// 用户选择了限时授权时返回
+    pub refresh_token_expires_in: Option<i64>,
+    pub scope: Option<String>,
+    pub id_token: Option<Secret>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope.unwrap_or_default()),
+            id_token: token.id_token,
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+        .with_refresh_expires_in(token.refresh_token_expires_in.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {}
+
+/// https://developers.google.com/identity/openid-connect/openid-connect#obtaininguserprofileinformation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// OAuth接口返回`{"error":"invalid_grant","error_description":"..."}`，
+/// 其他API返回`{"error":{"code":401,"message":"...","status":"UNAUTHENTICATED"}}`
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<ErrorDetail>,
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Code(String),
+    Status {
+        status: Option<String>,
+        message: String,
+    },
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match self.error? {
+            ErrorDetail::Code(error) => Some((error, self.error_description.unwrap_or_default())),
+            ErrorDetail::Status { status, message } => Some((status.unwrap_or_default(), message)),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! 各平台共用的HTTP客户端配置
+use crate::error::Result;
+use std::time::Duration;
+
+pub const DEFAULT_USER_AGENT: &str = concat!("just-auth/", env!("CARGO_PKG_VERSION"));
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 带默认超时和User-Agent的ClientBuilder，可以在此基础上自定义后通过builder的`http_client`传入
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .user_agent(DEFAULT_USER_AGENT)
+        .timeout(DEFAULT_TIMEOUT)
+        .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+}
+
+/// 使用默认配置创建HTTP客户端，克隆后可在多个平台之间共享连接池
+pub fn default_client() -> Result<reqwest::Client> {
+    Ok(client_builder().build()?)
+}
+
+pub(crate) fn build_client(
+    user_agent: Option<String>,
+    timeout: Option<Duration>,
+    proxy: Option<reqwest::Proxy>,
+) -> Result<reqwest::Client> {
+    let mut builder = client_builder();
+    if let Some(user_agent) = user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build()?)
+}
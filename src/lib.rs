@@ -1,38 +1,257 @@
+pub mod apple;
 pub mod baidu;
+pub mod credential;
+pub mod dingtalk;
 pub mod error;
 pub mod facebook;
+pub mod feishu;
+pub mod gitee;
 pub mod github;
+pub mod gitlab;
+pub mod google;
+pub mod http;
+pub mod microsoft;
+pub mod oidc;
+pub mod pkce;
 pub mod qq;
+pub mod registry;
+pub mod secret;
+pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod twitter;
+pub mod wechat;
+pub mod wechat_miniprogram;
+pub mod wechat_mp;
 pub mod wechat_open;
+pub mod wecom;
 pub mod weibo;
 
 mod utils;
 
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::error::Result;
+use crate::error::{AuthError, Result, StateError};
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeVerifier};
+use crate::secret::Secret;
+use crate::state::{StateData, StateStore};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use serde_with::{serde_as, TimestampSeconds};
 
 pub struct AuthConfig {
     client_id: String,
-    client_secret: Option<String>,
+    client_secret: Option<Secret>,
     redirect_uri: String,
     scope: Option<Vec<String>>,
+    /// 平台名称，作为state在StateStore中的命名空间
+    provider: &'static str,
+    state_store: Arc<dyn StateStore>,
+    http_client: reqwest::Client,
 }
 
+impl AuthConfig {
+    fn validate(&self, client_secret_required: bool, redirect_uri_required: bool) -> Result<()> {
+        if self.client_id.is_empty() {
+            return Err(AuthError::MissingConfig("client_id"));
+        }
+        if client_secret_required
+            && self
+                .client_secret
+                .as_ref()
+                .is_none_or(|secret| secret.secret().is_empty())
+        {
+            return Err(AuthError::MissingConfig("client_secret"));
+        }
+        if self.redirect_uri.is_empty() {
+            if redirect_uri_required {
+                return Err(AuthError::MissingConfig("redirect_uri"));
+            }
+            return Ok(());
+        }
+        url::Url::parse(&self.redirect_uri).map_err(|e| AuthError::InvalidConfig {
+            field: "redirect_uri",
+            source: e.into(),
+        })?;
+        Ok(())
+    }
+
+    /// 请求参数中也以[`Secret`]保存client_secret，`Debug`不会输出，drop时清零。
+    /// 序列化后的请求url和body由reqwest持有，不在清零范围内
+    fn client_secret(&self) -> Result<Secret> {
+        self.client_secret
+            .clone()
+            .ok_or(AuthError::MissingConfig("client_secret"))
+    }
+
+    /// 不同平台的state互不干扰，一个平台签发的state不能用于另一个平台的回调
+    fn state_key(&self, state: &str) -> String {
+        format!("{}:{state}", self.provider)
+    }
+
+    /// 保存授权state
+    async fn save_state(&self, state: &str, data: StateData) -> Result<()> {
+        self.state_store.save(&self.state_key(state), data).await
+    }
+
+    /// 保存state，按需生成PKCE和nonce一起保存，回调时从StateStore取回code_verifier和nonce
+    async fn begin_authorization(
+        &self,
+        state: &str,
+        pkce: bool,
+        nonce: bool,
+    ) -> Result<Authorization> {
+        let (code_challenge, code_verifier) = match pkce {
+            true => {
+                let (code_challenge, code_verifier) = PkceCodeChallenge::new_random_s256();
+                (Some(code_challenge), Some(code_verifier))
+            }
+            false => (None, None),
+        };
+        let nonce = nonce.then(state::generate);
+        let data = StateData {
+            code_verifier,
+            nonce: nonce.clone(),
+        };
+        self.save_state(state, data).await?;
+        Ok(Authorization {
+            code_challenge,
+            nonce,
+        })
+    }
+
+    /// 使用调用方生成的code_challenge，不保存code_verifier，按需生成nonce和state一起保存
+    async fn begin_authorization_with_pkce(
+        &self,
+        state: &str,
+        code_challenge: &PkceCodeChallenge,
+        nonce: bool,
+    ) -> Result<Authorization> {
+        let authorization = self.begin_authorization(state, false, nonce).await?;
+        Ok(Authorization {
+            code_challenge: Some(code_challenge.clone()),
+            ..authorization
+        })
+    }
+
+    /// 校验并作废回调中的state，取回[`AuthConfig::begin_authorization`]保存的code_verifier和nonce
+    async fn verify_state(&self, state: Option<&str>) -> Result<StateData> {
+        match state {
+            Some(state) => self.state_store.consume(&self.state_key(state)).await,
+            None => Err(AuthError::InvalidState(StateError::Missing)),
+        }
+    }
+
+    /// 解析支持PKCE的平台的回调并校验state，返回回调参数和授权时保存的nonce。
+    /// `code_verifier`为`login_with_pkce`传入的值，为`None`时使用随state保存的code_verifier
+    async fn verify_pkce_callback(
+        &self,
+        callback: &str,
+        code_verifier: Option<&PkceCodeVerifier>,
+    ) -> Result<(PkceAuthCallback, Option<String>)> {
+        let mut callback: PkceAuthCallback = serde_urlencoded::from_str(callback)?;
+        let data = self.verify_state(callback.state.as_deref()).await?;
+        callback.code_verifier = code_verifier
+            .or(data.code_verifier.as_ref())
+            .map(|v| v.secret().to_string());
+        Ok((callback, data.nonce))
+    }
+
+    /// OpenID Connect授权请求的scope，默认为`profile email`，总是包含`openid`
+    fn openid_scope(&self) -> Vec<String> {
+        let mut scope = self
+            .scope
+            .clone()
+            .unwrap_or_else(|| vec!["profile".into(), "email".into()]);
+        if !scope.iter().any(|s| s == "openid") {
+            scope.insert(0, "openid".into());
+        }
+        scope
+    }
+}
+
+/// [`AuthConfig::begin_authorization`]生成的、需要放到授权url中的参数
+#[derive(Default)]
+struct Authorization {
+    code_challenge: Option<PkceCodeChallenge>,
+    nonce: Option<String>,
+}
+
+impl Authorization {
+    fn code_challenge(&self) -> Option<String> {
+        self.code_challenge.as_ref().map(|c| c.as_str().to_string())
+    }
+
+    fn code_challenge_method(&self) -> Option<String> {
+        self.code_challenge
+            .as_ref()
+            .map(|c| c.method().as_str().to_string())
+    }
+}
+
+/// 生成`AuthorizationServer`的builder，`build`时会校验配置，
+/// 不需要client_secret的公共客户端使用`auth_server_builder!(public_client)`
+///
+/// 有平台特有配置或运行时缓存的平台使用`auth_server_builder!(options = Options)`，
+/// builder会增加`options`方法，`build`时调用`AuthorizationServer::new(config, endpoints, options)`
+///
+/// 没有授权回调的平台，如微信小程序，使用`auth_server_builder!(without_redirect, options = Options)`，
+/// `build`时不要求redirect_uri
 #[macro_export]
 macro_rules! auth_server_builder {
     () => {
+        $crate::auth_server_builder!(@builder true, true);
+    };
+    (public_client) => {
+        $crate::auth_server_builder!(@builder false, true);
+    };
+    (options = $options:ty) => {
+        $crate::auth_server_builder!(@builder true, true, $options);
+    };
+    (public_client, options = $options:ty) => {
+        $crate::auth_server_builder!(@builder false, true, $options);
+    };
+    (without_redirect, options = $options:ty) => {
+        $crate::auth_server_builder!(@builder true, false, $options);
+    };
+    (@new $config:ident, $endpoints:expr) => {
+        Ok(AuthorizationServer {
+            config: $config,
+            endpoints: $endpoints,
+        })
+    };
+    (@new $config:ident, $endpoints:expr, $options:expr) => {
+        AuthorizationServer::new($config, $endpoints, $options)
+    };
+    (@builder $client_secret_required:expr, $redirect_uri_required:expr $(, $options:ty)?) => {
         #[derive(Default)]
         pub struct AuthConfigBuilder {
             client_id: Option<String>,
-            client_secret: Option<String>,
+            client_secret: Option<$crate::secret::Secret>,
             redirect_uri: Option<String>,
             scope: Option<Vec<String>>,
+            state_store: Option<std::sync::Arc<dyn $crate::state::StateStore>>,
+            http_client: Option<reqwest::Client>,
+            user_agent: Option<String>,
+            timeout: Option<std::time::Duration>,
+            proxy: Option<reqwest::Proxy>,
+            endpoints: Option<Endpoints>,
+            $(options: Option<$options>,)?
         }
 
+        $(
+            impl AuthConfigBuilder {
+                /// 平台特有的配置
+                pub fn options(mut self, options: $options) -> Self {
+                    self.options = Some(options);
+                    self
+                }
+            }
+        )?
+
         impl AuthorizationServer {
             pub fn builder() -> AuthConfigBuilder {
                 AuthConfigBuilder::default()
@@ -45,7 +264,7 @@ macro_rules! auth_server_builder {
                 self
             }
             pub fn client_secret<S: Into<String>>(mut self, client_secret: S) -> Self {
-                self.client_secret = Some(client_secret.into());
+                self.client_secret = Some($crate::secret::Secret::new(client_secret));
                 self
             }
             pub fn redirect_uri<S: Into<String>>(mut self, redirect_uri: S) -> Self {
@@ -59,15 +278,61 @@ macro_rules! auth_server_builder {
                 }
                 self
             }
-            pub fn build(self) -> AuthorizationServer {
-                AuthorizationServer {
-                    config: AuthConfig {
-                        client_id: self.client_id.unwrap_or_default(),
-                        client_secret: self.client_secret,
-                        redirect_uri: self.redirect_uri.unwrap_or_default(),
-                        scope: self.scope,
+            /// 保存授权state，用于在登录时拒绝未知、过期和重复使用的state，
+            /// 自动生成的PKCE code_verifier和OIDC nonce也随state保存。
+            ///
+            /// 未配置时使用每个`AuthorizationServer`独立的[`crate::state::MemoryStateStore`]，
+            /// 多实例部署或每次请求都重新build时需要配置共享的StateStore
+            pub fn state_store(
+                mut self,
+                state_store: std::sync::Arc<dyn $crate::state::StateStore>,
+            ) -> Self {
+                self.state_store = Some(state_store);
+                self
+            }
+            /// 使用共享的HTTP客户端，设置后会忽略user_agent、timeout和proxy
+            pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+                self.http_client = Some(http_client);
+                self
+            }
+            pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+                self.user_agent = Some(user_agent.into());
+                self
+            }
+            pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+                self.timeout = Some(timeout);
+                self
+            }
+            pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+                self.proxy = Some(proxy);
+                self
+            }
+            /// 自定义接口地址，用于私有部署、升级API版本或指向测试服务
+            pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+                self.endpoints = Some(endpoints);
+                self
+            }
+            pub fn build(self) -> $crate::error::Result<AuthorizationServer> {
+                let config = AuthConfig {
+                    client_id: self.client_id.unwrap_or_default(),
+                    client_secret: self.client_secret,
+                    redirect_uri: self.redirect_uri.unwrap_or_default(),
+                    scope: self.scope,
+                    provider: AuthorizationServer::PROVIDER,
+                    state_store: self.state_store.unwrap_or_else(|| {
+                        std::sync::Arc::new($crate::state::MemoryStateStore::default())
+                    }),
+                    http_client: match self.http_client {
+                        Some(http_client) => http_client,
+                        None => $crate::http::build_client(self.user_agent, self.timeout, self.proxy)?,
                     },
-                }
+                };
+                config.validate($client_secret_required, $redirect_uri_required)?;
+                $crate::auth_server_builder!(
+                    @new config,
+                    self.endpoints.unwrap_or_default()
+                    $(, self.options.unwrap_or_else(<$options>::default))?
+                )
             }
         }
     };
@@ -80,15 +345,16 @@ pub trait AuthUrlProvider {
     /// 返回带redirect_ui和state参数的授权url，授权回调时会带上这个state。
     /// 用户端重定向至该URL地址进行认证授权
     ///
-    fn authorize_url(request: Self::AuthRequest) -> Result<String>;
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String>;
 
-    /// 返回获取accessToken的url
+    /// 返回获取accessToken的url，以POST提交请求参数的平台只返回接口地址，
+    /// 调用方再以表单或JSON提交同一个请求
     ///
-    fn access_token_url(request: Self::TokenRequest) -> Result<String>;
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String>;
 
     /// 返回获取userInfo的url
     ///
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String>;
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String>;
 }
 
 #[async_trait]
@@ -100,20 +366,522 @@ pub trait AuthAction {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken>;
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser>;
+
+    /// 使用refresh_token刷新accessToken，不支持刷新的平台返回[`AuthError::Unsupported`]
+    ///
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        _refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        Err(AuthError::Unsupported("refresh_token"))
+    }
 }
 
 #[async_trait]
 pub trait GenericAuthAction {
+    /// 平台名称，如`github`、`qq`，用作[`registry::ProviderRegistry`]中的默认key
+    const PROVIDER: &'static str;
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String>;
 
+    /// 生成随机的state并返回`(授权url, state)`，
+    /// 调用方将state保存在浏览器会话中，如cookie，回调时通过[`GenericAuthAction::login_with_state`]比对
+    ///
+    async fn authorize_with_random_state(&self) -> Result<(String, String)>
+    where
+        Self: Sync,
+    {
+        let state = state::generate();
+        let url = self.authorize(state.clone()).await?;
+        Ok((url, state))
+    }
+
     async fn login<S: Into<String> + Send>(&self, callback_raw_query: S) -> Result<AuthUser>;
+
+    /// 先比对回调中的state与发起授权的浏览器会话中保存的state，再调用[`GenericAuthAction::login`]。
+    /// StateStore只能识别未知、过期和重复使用的state，绑定到浏览器会话才能防止CSRF攻击
+    ///
+    async fn login_with_state<S: Into<String> + Send>(
+        &self,
+        callback_raw_query: S,
+        expected_state: &str,
+    ) -> Result<AuthUser>
+    where
+        Self: Sync,
+    {
+        let callback = callback_raw_query.into();
+        state::check_callback(&callback, expected_state)?;
+        self.login(callback).await
+    }
+
+    /// 返回带PKCE code_challenge的授权url，对应的code_verifier由调用方保存，
+    /// 授权回调时通过[`GenericAuthAction::login_with_pkce`]提交
+    ///
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        _state: S,
+        _code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        Err(AuthError::Unsupported("pkce"))
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        _callback_raw_query: S,
+        _code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        Err(AuthError::Unsupported("pkce"))
+    }
+
+    /// 刷新accessToken，并用新的accessToken重新获取用户信息
+    ///
+    async fn refresh<S: Into<String> + Send>(&self, _refresh_token: S) -> Result<AuthUser> {
+        Err(AuthError::Unsupported("refresh_token"))
+    }
+
+    /// 在平台撤销授权，用于退出登录或解除账号绑定，不支持的平台返回[`AuthError::Unsupported`]
+    ///
+    async fn revoke<S: Into<String> + Send>(&self, _token: S) -> Result<()> {
+        Err(AuthError::Unsupported("revoke"))
+    }
 }
 
+/// [`GenericAuthAction`]的对象安全版本，可以作为`dyn AuthProvider`在运行时按平台名称分发，
+/// 所有实现了[`GenericAuthAction`]的平台都自动实现了该trait
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    fn provider(&self) -> &'static str;
+
+    async fn authorize(&self, state: String) -> Result<String>;
+
+    async fn authorize_with_random_state(&self) -> Result<(String, String)>;
+
+    async fn login(&self, callback_raw_query: String) -> Result<AuthUser>;
+
+    async fn login_with_state(
+        &self,
+        callback_raw_query: String,
+        expected_state: &str,
+    ) -> Result<AuthUser>;
+
+    async fn authorize_with_pkce(
+        &self,
+        state: String,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String>;
+
+    async fn login_with_pkce(
+        &self,
+        callback_raw_query: String,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser>;
+
+    async fn refresh(&self, refresh_token: String) -> Result<AuthUser>;
+
+    async fn revoke(&self, token: String) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: GenericAuthAction + Send + Sync> AuthProvider for T {
+    fn provider(&self) -> &'static str {
+        T::PROVIDER
+    }
+
+    async fn authorize(&self, state: String) -> Result<String> {
+        GenericAuthAction::authorize(self, state).await
+    }
+
+    async fn authorize_with_random_state(&self) -> Result<(String, String)> {
+        GenericAuthAction::authorize_with_random_state(self).await
+    }
+
+    async fn login(&self, callback_raw_query: String) -> Result<AuthUser> {
+        GenericAuthAction::login(self, callback_raw_query).await
+    }
+
+    async fn login_with_state(
+        &self,
+        callback_raw_query: String,
+        expected_state: &str,
+    ) -> Result<AuthUser> {
+        GenericAuthAction::login_with_state(self, callback_raw_query, expected_state).await
+    }
+
+    async fn authorize_with_pkce(
+        &self,
+        state: String,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        GenericAuthAction::authorize_with_pkce(self, state, code_challenge).await
+    }
+
+    async fn login_with_pkce(
+        &self,
+        callback_raw_query: String,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        GenericAuthAction::login_with_pkce(self, callback_raw_query, code_verifier).await
+    }
+
+    async fn refresh(&self, refresh_token: String) -> Result<AuthUser> {
+        GenericAuthAction::refresh(self, refresh_token).await
+    }
+
+    async fn revoke(&self, token: String) -> Result<()> {
+        GenericAuthAction::revoke(self, token).await
+    }
+}
+
+/// 为`#[serde(remote = "Self")]`的类型实现带版本号的序列化格式，如`{"v":"1",...}`，
+/// 字段不兼容的变更需要增加新的版本。
+/// 新版本能直接读取的旧版本通过`compatible = [...]`列出，旧版本的读取方会拒绝新版本
+macro_rules! versioned_serde {
+    ($ty:ident, $version:literal $(, compatible = [$($compatible:literal),*])?) => {
+        const _: () = {
+            use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+            #[derive(Serialize)]
+            #[serde(tag = "v")]
+            enum VersionedRef<'a> {
+                #[serde(rename = $version)]
+                Current(#[serde(serialize_with = "serialize_current")] &'a $ty),
+            }
+
+            #[derive(Deserialize)]
+            #[serde(tag = "v")]
+            enum Versioned {
+                #[serde(rename = $version $($(, alias = $compatible)*)?)]
+                Current(#[serde(deserialize_with = "deserialize_current")] $ty),
+            }
+
+            fn serialize_current<S: Serializer>(
+                value: &&$ty,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                $ty::serialize(value, serializer)
+            }
+
+            fn deserialize_current<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<$ty, D::Error> {
+                $ty::deserialize(deserializer)
+            }
+
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> std::result::Result<S::Ok, S::Error> {
+                    VersionedRef::Current(self).serialize(serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(
+                    deserializer: D,
+                ) -> std::result::Result<Self, D::Error> {
+                    match Versioned::deserialize(deserializer)? {
+                        Versioned::Current(value) => Ok(value),
+                    }
+                }
+            }
+        };
+    };
+}
+
+/// 统一的用户信息，平台未返回的字段为`None`，
+/// 序列化格式带有版本号，可以保存到session中
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct AuthUser {
+    /// 平台名称，同[`GenericAuthAction::PROVIDER`]
+    pub provider: String,
     pub user_id: String,
     pub name: String,
-    pub access_token: String,
-    pub refresh_token: String,
-    pub expires_in: i64,
+    /// 登录名，如github的login、twitter的username
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub avatar: Option<String>,
+    pub gender: Option<Gender>,
+    pub locale: Option<String>,
+    pub location: Option<String>,
+    /// 用户主页地址
+    pub profile_url: Option<String>,
+    /// 用户所属的租户或企业，如微软账号的tid
+    pub tenant_id: Option<String>,
+    /// 用户在租户内的ID，如飞书的user_id
+    pub tenant_user_id: Option<String>,
+    /// 同一开发者主体下多个应用间统一的用户ID，如飞书的union_id
+    pub union_id: Option<String>,
+    /// 用户在当前应用内的ID，如微信的openid，同一用户在不同应用中不同
+    pub open_id: Option<String>,
+    /// 平台没有签发用户的accessToken时为`None`，如企业微信
+    pub token: Option<AuthToken>,
+    /// 平台返回的原始用户信息
     pub extra: HashMap<String, Value>,
 }
+
+// 版本2中`token`改为可选，版本1的`token`总是存在，可以直接读取
+versioned_serde!(AuthUser, "2", compatible = ["1"]);
+
+/// 统一的token信息，有效期记录为绝对时间，便于持久化和提前刷新，
+/// 时间序列化为unix时间戳(秒)
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct AuthToken {
+    pub access_token: Secret,
+    pub token_type: Option<String>,
+    pub refresh_token: Option<Secret>,
+    /// 实际授予的scope
+    pub scopes: Vec<String>,
+    pub id_token: Option<Secret>,
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    pub issued_at: SystemTime,
+    /// 平台未返回有效期时为`None`
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    pub expires_at: Option<SystemTime>,
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    pub refresh_expires_at: Option<SystemTime>,
+}
+
+versioned_serde!(AuthToken, "1");
+
+impl Default for AuthToken {
+    fn default() -> Self {
+        Self {
+            access_token: Secret::default(),
+            token_type: None,
+            refresh_token: None,
+            scopes: Vec::new(),
+            id_token: None,
+            issued_at: SystemTime::now(),
+            expires_at: None,
+            refresh_expires_at: None,
+        }
+    }
+}
+
+impl AuthToken {
+    /// 以当前时间作为签发时间
+    pub fn new<S: Into<Secret>>(access_token: S) -> Self {
+        Self {
+            access_token: access_token.into(),
+            ..Default::default()
+        }
+    }
+
+    /// 按平台返回的有效期(秒)从签发时间起计算过期时间，非正数视为不过期
+    pub fn with_expires_in(mut self, expires_in: i64) -> Self {
+        self.expires_at = expires_at(self.issued_at, expires_in);
+        self
+    }
+
+    pub fn with_refresh_expires_in(mut self, expires_in: i64) -> Self {
+        self.refresh_expires_at = expires_at(self.issued_at, expires_in);
+        self
+    }
+
+    /// accessToken剩余的有效期，已过期时为0
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_at.map(|at| {
+            at.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    /// `skew`时间内将要过期也视为已过期，用于提前刷新
+    pub fn is_expired(&self, skew: Duration) -> bool {
+        is_expired(self.expires_at, skew)
+    }
+
+    pub fn is_refresh_expired(&self, skew: Duration) -> bool {
+        is_expired(self.refresh_expires_at, skew)
+    }
+}
+
+fn expires_at(issued_at: SystemTime, expires_in: i64) -> Option<SystemTime> {
+    u64::try_from(expires_in)
+        .ok()
+        .filter(|secs| *secs > 0)
+        .and_then(|secs| issued_at.checked_add(Duration::from_secs(secs)))
+}
+
+fn is_expired(expires_at: Option<SystemTime>, skew: Duration) -> bool {
+    match expires_at {
+        Some(expires_at) => SystemTime::now()
+            .checked_add(skew)
+            .is_none_or(|now| now >= expires_at),
+        None => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gender {
+    Male,
+    Female,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn versioned_auth_user() {
+        let user = AuthUser {
+            provider: "github".into(),
+            user_id: "1".into(),
+            name: "octocat".into(),
+            gender: Some(Gender::Female),
+            token: Some(AuthToken::new("access-token").with_expires_in(3600)),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&user).unwrap();
+        assert_eq!(value["v"], "2");
+        assert_eq!(value["gender"], "female");
+        assert_eq!(value["token"]["v"], "1");
+        assert_eq!(value["token"]["access_token"], "access-token");
+
+        let user: AuthUser = serde_json::from_value(value).unwrap();
+        assert_eq!(user.user_id, "1");
+        assert_eq!(user.gender, Some(Gender::Female));
+        let token = user.token.unwrap();
+        assert_eq!(token.access_token.secret(), "access-token");
+        assert!(token.expires_at.is_some());
+    }
+
+    #[test]
+    fn compatible_auth_user_version() {
+        let value = json!({
+            "v": "1",
+            "provider": "github",
+            "user_id": "1",
+            "name": "octocat",
+            "username": null,
+            "email": null,
+            "email_verified": null,
+            "avatar": null,
+            "gender": null,
+            "locale": null,
+            "location": null,
+            "profile_url": null,
+            "tenant_id": null,
+            "tenant_user_id": null,
+            "union_id": null,
+            "token": {"v": "1", "access_token": "access-token", "token_type": null, "refresh_token": null, "scopes": [], "id_token": null, "issued_at": 0, "expires_at": null, "refresh_expires_at": null},
+            "extra": {},
+        });
+        let user: AuthUser = serde_json::from_value(value).unwrap();
+        assert_eq!(user.token.unwrap().access_token.secret(), "access-token");
+
+        let user = AuthUser::default();
+        let value = serde_json::to_value(&user).unwrap();
+        assert_eq!(value["v"], "2");
+        assert!(value["token"].is_null());
+    }
+
+    #[test]
+    fn unknown_version() {
+        let value = json!({"v": "0", "access_token": "access-token"});
+        assert!(serde_json::from_value::<AuthToken>(value).is_err());
+        let value = json!({"access_token": "access-token"});
+        assert!(serde_json::from_value::<AuthToken>(value).is_err());
+    }
+
+    #[test]
+    fn token_timestamps() {
+        let token = AuthToken::new("access-token").with_expires_in(3600);
+        let value = serde_json::to_value(&token).unwrap();
+        let issued_at = value["issued_at"].as_i64().unwrap();
+        assert_eq!(value["expires_at"].as_i64().unwrap(), issued_at + 3600);
+        assert_eq!(value["refresh_expires_at"], Value::Null);
+    }
+
+    #[test]
+    fn expires_in() {
+        let token = AuthToken::new("access-token").with_expires_in(3600);
+        assert!(!token.is_expired(Duration::ZERO));
+        assert!(token.is_expired(Duration::from_secs(3600)));
+        // 非正数和溢出的有效期视为不过期
+        assert!(AuthToken::new("").with_expires_in(0).expires_at.is_none());
+        assert!(AuthToken::new("").with_expires_in(-1).expires_at.is_none());
+        assert!(AuthToken::new("")
+            .with_expires_in(i64::MAX)
+            .expires_at
+            .is_none());
+    }
+
+    fn config(client_id: &str, client_secret: Option<&str>, redirect_uri: &str) -> AuthConfig {
+        AuthConfig {
+            client_id: client_id.into(),
+            client_secret: client_secret.map(Secret::new),
+            redirect_uri: redirect_uri.into(),
+            scope: None,
+            provider: "test",
+            state_store: Arc::new(state::MemoryStateStore::default()),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    #[test]
+    fn validate_config() {
+        const REDIRECT_URI: &str = "http://localhost/callback";
+        config("client-id", Some("secret"), REDIRECT_URI)
+            .validate(true, true)
+            .unwrap();
+        // 公共客户端不要求client_secret，没有授权回调的平台不要求redirect_uri
+        config("client-id", None, REDIRECT_URI)
+            .validate(false, true)
+            .unwrap();
+        config("client-id", Some("secret"), "")
+            .validate(true, false)
+            .unwrap();
+
+        let error = config("", Some("secret"), REDIRECT_URI)
+            .validate(true, true)
+            .unwrap_err();
+        assert!(
+            matches!(error, AuthError::MissingConfig("client_id")),
+            "{error:?}"
+        );
+        for client_secret in [None, Some("")] {
+            let error = config("client-id", client_secret, REDIRECT_URI)
+                .validate(true, true)
+                .unwrap_err();
+            assert!(
+                matches!(error, AuthError::MissingConfig("client_secret")),
+                "{error:?}"
+            );
+        }
+        let error = config("client-id", Some("secret"), "")
+            .validate(true, true)
+            .unwrap_err();
+        assert!(
+            matches!(error, AuthError::MissingConfig("redirect_uri")),
+            "{error:?}"
+        );
+        let error = config("client-id", Some("secret"), "localhost/callback")
+            .validate(true, true)
+            .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                AuthError::InvalidConfig {
+                    field: "redirect_uri",
+                    ..
+                }
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn expired_skew_overflow() {
+        let token = AuthToken::new("access-token").with_expires_in(3600);
+        assert!(token.is_expired(Duration::MAX));
+        assert!(!AuthToken::new("access-token").is_expired(Duration::MAX));
+    }
+}
@@ -0,0 +1,424 @@
+//! https://learn.microsoft.com/entra/identity-platform/v2-oauth2-auth-code-flow
+//! https://learn.microsoft.com/entra/identity-platform/id-token-claims-reference
+//!
+//! 微软身份平台(Azure AD / Entra ID)的v2.0接口，支持个人账号和工作或学校账号。
+//! 用户信息取自id_token，`user_id`为oid，`tenant_id`为tid。刷新时同样要求返回id_token，
+//! 保证刷新前后的`user_id`一致，并按[`Options::allowed_tenants`]校验租户
+use crate::error::{IdTokenError, Result};
+use crate::oidc::{self, IdTokenClaims, IdTokenVerifier};
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeVerifier};
+use crate::secret::Secret;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Authorization, GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{formats::SpaceSeparator, serde_as, StringWithSeparator};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+    verifier: IdTokenVerifier,
+}
+
+auth_server_builder!(public_client, options = Options);
+
+/// 各接口地址，默认为全球版Azure的`common`租户
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub jwks_url: String,
+    /// id_token的签发方，`{tenantid}`会替换为id_token中的tid后再比较
+    pub issuer: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self::new(Cloud::Global, "common")
+    }
+}
+
+impl Endpoints {
+    /// `tenant`为`common`、`organizations`、`consumers`，或者租户的GUID、域名
+    pub fn new<S: AsRef<str>>(cloud: Cloud, tenant: S) -> Self {
+        let tenant = tenant.as_ref();
+        let authority = format!("https://{}/{tenant}", cloud.authority_host());
+        Self {
+            authorize_url: format!("{authority}/oauth2/v2.0/authorize"),
+            access_token_url: format!("{authority}/oauth2/v2.0/token"),
+            user_info_url: format!("https://{}/v1.0/me", cloud.graph_host()),
+            jwks_url: format!("{authority}/discovery/v2.0/keys"),
+            issuer: format!("https://{}/{{tenantid}}/v2.0", cloud.authority_host()),
+        }
+    }
+}
+
+/// https://learn.microsoft.com/entra/identity-platform/authentication-national-cloud
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cloud {
+    Global,
+    /// Azure美国政府云
+    UsGovernment,
+    /// 世纪互联运营的Azure中国
+    China,
+}
+
+impl Cloud {
+    pub fn authority_host(&self) -> &'static str {
+        match self {
+            Self::Global => "login.microsoftonline.com",
+            Self::UsGovernment => "login.microsoftonline.us",
+            Self::China => "login.chinacloudapi.cn",
+        }
+    }
+
+    pub fn graph_host(&self) -> &'static str {
+        match self {
+            Self::Global => "graph.microsoft.com",
+            Self::UsGovernment => "graph.microsoft.us",
+            Self::China => "microsoftgraph.chinacloudapi.cn",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 允许登录的租户ID，为空时不限制。多租户应用登录后会校验id_token中的tid
+    pub allowed_tenants: Vec<String>,
+    /// `login`、`none`、`consent`或`select_account`
+    pub prompt: Option<String>,
+    /// `consumers`、`organizations`或租户域名，跳过账号类型的选择
+    pub domain_hint: Option<String>,
+    /// 见[`IdTokenVerifier::leeway`]
+    pub leeway: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            allowed_tenants: Vec::new(),
+            prompt: None,
+            domain_hint: None,
+            leeway: oidc::DEFAULT_LEEWAY,
+        }
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?response_type=code&response_mode=query&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 获取accessToken需要以POST表单提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
+    }
+
+    fn user_info_url(&self, _request: Self::UserInfoRequest) -> Result<String> {
+        Ok(self.endpoints.user_info_url.clone())
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = PkceAuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret().ok(),
+            code: callback.code,
+            redirect_uri: redirect_uri.to_string(),
+            code_verifier: callback.code_verifier,
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .config
+            .http_client
+            .post(access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 通过Microsoft Graph获取用户资料，需要`User.Read`权限
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {})?;
+        let response = self
+            .config
+            .http_client
+            .get(user_info_url)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 授权时scope需要包含`offline_access`才会返回refresh_token
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.config.client_secret().ok(),
+            refresh_token: refresh_token.into(),
+            scope: self.config.openid_scope(),
+        };
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.access_token_url)
+            .form(&request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "microsoft";
+
+    /// 自动生成nonce和PKCE，和state一起保存
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        let authorization = self.config.begin_authorization(&state, true, true).await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), None)
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref()).await
+    }
+
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        state: S,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        let state = state.into();
+        let authorization = self
+            .config
+            .begin_authorization_with_pkce(&state, code_challenge, true)
+            .await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        callback: S,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), Some(code_verifier))
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref()).await
+    }
+
+    /// 刷新时请求的scope包含`openid`，返回的id_token不带nonce，没有返回id_token时返回错误
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token, None).await
+    }
+}
+
+impl AuthorizationServer {
+    /// 多租户的iss随tid变化，由[`AuthorizationServer::verify_tenant`]校验
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        let verifier = IdTokenVerifier::new(Self::PROVIDER, &endpoints.jwks_url, &config.client_id)
+            .leeway(options.leeway);
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+            verifier,
+        })
+    }
+
+    fn build_authorize_url(&self, state: String, authorization: Authorization) -> Result<String> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: self.config.openid_scope(),
+            state,
+            code_challenge: authorization.code_challenge(),
+            code_challenge_method: authorization.code_challenge_method(),
+            nonce: authorization.nonce,
+            prompt: self.options.prompt.clone(),
+            domain_hint: self.options.domain_hint.clone(),
+        })
+    }
+
+    async fn auth_user(&self, token: TokenResponse, nonce: Option<&str>) -> Result<AuthUser> {
+        let id_token = token.id_token.as_ref().ok_or(IdTokenError::Missing)?;
+        let claims = self
+            .verifier
+            .verify(&self.config.http_client, id_token.secret(), nonce)
+            .await?;
+        let tenant_id = self.verify_tenant(&claims)?;
+        let oid = utils::get_str(&claims.extra, "oid");
+        let mut user = oidc::standard_user(Self::PROVIDER, claims.sub, claims.extra, token.into());
+        if let Some(oid) = oid {
+            user.user_id = oid;
+        }
+        user.tenant_id = Some(tenant_id);
+        Ok(user)
+    }
+
+    /// 按tid校验iss，配置了[`Options::allowed_tenants`]时校验tid是否在其中
+    fn verify_tenant(&self, claims: &IdTokenClaims) -> Result<String> {
+        let tenant_id =
+            utils::get_str(&claims.extra, "tid").ok_or(IdTokenError::ClaimMismatch("tid"))?;
+        if claims.iss != self.endpoints.issuer.replace("{tenantid}", &tenant_id) {
+            return Err(IdTokenError::ClaimMismatch("iss").into());
+        }
+        let allowed_tenants = &self.options.allowed_tenants;
+        if !allowed_tenants.is_empty()
+            && !allowed_tenants
+                .iter()
+                .any(|tenant| tenant.eq_ignore_ascii_case(&tenant_id))
+        {
+            return Err(IdTokenError::ClaimMismatch("tid").into());
+        }
+        Ok(tenant_id)
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    client_id: String,
+    redirect_uri: String,
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    prompt: Option<String>,
+    domain_hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    client_id: String,
+    /// 公共客户端不需要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+    code: String,
+    redirect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<String>,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+    refresh_token: String,
+    /// 包含`openid`时才会返回id_token
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: String,
+    pub expires_in: Option<i64>,
+    pub scope: Option<String>,
+    pub refresh_token: Option<Secret>,
+    pub id_token: Option<Secret>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope.unwrap_or_default()),
+            id_token: token.id_token,
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {}
+
+/// https://learn.microsoft.com/graph/api/user-get
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    /// 即id_token中的oid
+    pub id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// 登录接口返回`{"error":"invalid_grant","error_description":"..."}`，
+/// Graph接口返回`{"error":{"code":"InvalidAuthenticationToken","message":"..."}}`
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<ErrorDetail>,
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Code(String),
+    Graph { code: String, message: String },
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match self.error? {
+            ErrorDetail::Code(error) => Some((error, self.error_description.unwrap_or_default())),
+            ErrorDetail::Graph { code, message } => Some((code, message)),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+//! https://openid.net/specs/openid-connect-core-1_0.html
+//! https://openid.net/specs/openid-connect-discovery-1_0.html
+//!
+//! 通用的OpenID Connect登录，适用于Keycloak、Authing、Okta、Auth0等标准的OIDC服务。
+//! 接口地址从`{issuer}/.well-known/openid-configuration`获取，
+//! 会自动生成nonce和PKCE，和state一起保存到StateStore，登录时校验id_token中的nonce
+use crate::error::{AuthError, IdTokenError, Result};
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeChallengeMethod, PkceCodeVerifier};
+use crate::secret::Secret;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUser, Authorization, Gender,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{
+    formats::SpaceSeparator, serde_as, DisplayFromStr, OneOrMany, PickFirst, StringWithSeparator,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+    metadata: OnceCell<ProviderMetadata>,
+    verifier: OnceCell<IdTokenVerifier>,
+}
+
+auth_server_builder!(public_client, options = Options);
+
+/// OIDC服务的issuer，其他接口地址通过discovery获取
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Endpoints {
+    pub issuer: String,
+}
+
+impl Endpoints {
+    pub fn issuer<S: Into<String>>(issuer: S) -> Self {
+        Self {
+            issuer: issuer.into(),
+        }
+    }
+
+    fn discovery_url(&self) -> String {
+        format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer.trim_end_matches('/')
+        )
+    }
+}
+
+impl AuthConfigBuilder {
+    /// 等同于`endpoints(Endpoints::issuer(issuer))`
+    pub fn issuer<S: Into<String>>(self, issuer: S) -> Self {
+        self.endpoints(Endpoints::issuer(issuer))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 见[`IdTokenVerifier::leeway`]
+    pub leeway: Duration,
+    /// 是否通过userinfo接口补充用户信息，默认开启
+    pub fetch_userinfo: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            leeway: DEFAULT_LEEWAY,
+            fetch_userinfo: true,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = PkceAuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let metadata = self.metadata().await?;
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code: callback.code,
+            redirect_uri: redirect_uri.to_string(),
+            code_verifier: callback.code_verifier,
+            client_id: client_id.to_string(),
+            client_secret: self.post_client_secret(metadata),
+        };
+        let response = self
+            .token_request(&metadata.token_endpoint, &request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let metadata = self.metadata().await?;
+        let userinfo_endpoint = metadata
+            .userinfo_endpoint
+            .as_ref()
+            .ok_or(AuthError::Unsupported("userinfo"))?;
+        let response = self
+            .config
+            .http_client
+            .get(userinfo_endpoint)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let metadata = self.metadata().await?;
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.into(),
+            client_id: self.config.client_id.to_string(),
+            client_secret: self.post_client_secret(metadata),
+        };
+        let response = self
+            .token_request(&metadata.token_endpoint, &request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "oidc";
+
+    /// 生成nonce，服务端支持时还会生成PKCE，和state一起保存
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        let pkce = self.metadata().await?.supports_pkce();
+        let authorization = self.config.begin_authorization(&state, pkce, true).await?;
+        self.build_authorize_url(state, authorization).await
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), None)
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref(), true).await
+    }
+
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        state: S,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        let state = state.into();
+        let authorization = self
+            .config
+            .begin_authorization_with_pkce(&state, code_challenge, true)
+            .await?;
+        self.build_authorize_url(state, authorization).await
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        callback: S,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        let (callback, nonce) = self
+            .config
+            .verify_pkce_callback(&callback.into(), Some(code_verifier))
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token, nonce.as_deref(), true).await
+    }
+
+    /// 刷新时服务端不一定返回新的id_token，此时只从userinfo接口获取用户信息
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token, None, false).await
+    }
+
+    /// 服务端提供了revocation_endpoint时按RFC 7009撤销token
+    async fn revoke<S: Into<String> + Send>(&self, token: S) -> Result<()> {
+        let metadata = self.metadata().await?;
+        let revocation_endpoint = metadata
+            .revocation_endpoint
+            .as_ref()
+            .ok_or(AuthError::Unsupported("revoke"))?;
+        let token = token.into();
+        let client_secret = self.post_client_secret(metadata);
+        let request = utils::RevokeTokenRequest {
+            token: &token,
+            token_type_hint: None,
+            client_id: Some(&self.config.client_id),
+            client_secret: client_secret.as_ref().map(Secret::secret),
+        };
+        let response = self
+            .token_request(revocation_endpoint, &request)
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        if endpoints.issuer.is_empty() {
+            return Err(AuthError::MissingConfig("issuer"));
+        }
+        url::Url::parse(&endpoints.issuer).map_err(|e| AuthError::InvalidConfig {
+            field: "issuer",
+            source: e.into(),
+        })?;
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+            metadata: OnceCell::new(),
+            verifier: OnceCell::new(),
+        })
+    }
+
+    /// 首次调用时获取discovery文档并缓存
+    pub async fn metadata(&self) -> Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let response = self
+                    .config
+                    .http_client
+                    .get(self.endpoints.discovery_url())
+                    .send()
+                    .await?;
+                let metadata: ProviderMetadata =
+                    utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+                if metadata.issuer.trim_end_matches('/')
+                    != self.endpoints.issuer.trim_end_matches('/')
+                {
+                    return Err(AuthError::InvalidResponse {
+                        provider: Self::PROVIDER,
+                        message: format!(
+                            "issuer {} in discovery document does not match {}",
+                            metadata.issuer, self.endpoints.issuer
+                        ),
+                    });
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    async fn verifier(&self) -> Result<&IdTokenVerifier> {
+        let metadata = self.metadata().await?;
+        let verifier = self
+            .verifier
+            .get_or_init(|| async {
+                IdTokenVerifier::new(Self::PROVIDER, &metadata.jwks_uri, &self.config.client_id)
+                    .issuer(&metadata.issuer)
+                    .leeway(self.options.leeway)
+            })
+            .await;
+        Ok(verifier)
+    }
+
+    async fn build_authorize_url(
+        &self,
+        state: String,
+        authorization: Authorization,
+    ) -> Result<String> {
+        let metadata = self.metadata().await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        let query = serde_urlencoded::to_string(AuthRequest {
+            response_type: "code".to_string(),
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: self.config.openid_scope(),
+            state,
+            code_challenge: authorization.code_challenge(),
+            code_challenge_method: authorization.code_challenge_method(),
+            nonce: authorization.nonce,
+        })?;
+        Ok(format!("{}?{query}", metadata.authorization_endpoint))
+    }
+
+    /// 服务端只支持client_secret_post时在表单中提交client_secret
+    fn post_client_secret(&self, metadata: &ProviderMetadata) -> Option<Secret> {
+        match metadata.client_auth_method() {
+            ClientAuthMethod::Post => self.config.client_secret().ok(),
+            ClientAuthMethod::Basic => None,
+        }
+    }
+
+    /// 默认使用client_secret_basic认证，公共客户端只提交client_id
+    fn token_request<T: Serialize>(&self, url: &str, form: &T) -> reqwest::RequestBuilder {
+        let AuthConfig {
+            client_id,
+            client_secret,
+            ..
+        } = &self.config;
+        let request = self.config.http_client.post(url).form(form);
+        match (
+            self.metadata
+                .get()
+                .map(ProviderMetadata::client_auth_method),
+            client_secret,
+        ) {
+            (Some(ClientAuthMethod::Basic), Some(client_secret)) => {
+                request.basic_auth(client_id, Some(client_secret.secret()))
+            }
+            _ => request,
+        }
+    }
+
+    async fn auth_user(
+        &self,
+        token: TokenResponse,
+        nonce: Option<&str>,
+        id_token_required: bool,
+    ) -> Result<AuthUser> {
+        let claims = match &token.id_token {
+            Some(id_token) => {
+                let verifier = self.verifier().await?;
+                Some(
+                    verifier
+                        .verify(&self.config.http_client, id_token.secret(), nonce)
+                        .await?,
+                )
+            }
+            None if id_token_required => return Err(IdTokenError::Missing.into()),
+            None => None,
+        };
+        let metadata = self.metadata().await?;
+        let fetch_userinfo = self.options.fetch_userinfo || claims.is_none();
+        let userinfo = match metadata.userinfo_endpoint {
+            Some(_) if fetch_userinfo => Some(self.get_user_info(token.clone()).await?),
+            _ => None,
+        };
+        let (sub, claims) = match (claims, userinfo) {
+            (Some(claims), Some(userinfo)) => {
+                if claims.sub != userinfo.sub {
+                    return Err(IdTokenError::ClaimMismatch("sub").into());
+                }
+                let mut extra = claims.extra;
+                extra.extend(userinfo.extra);
+                (claims.sub, extra)
+            }
+            (Some(claims), None) => (claims.sub, claims.extra),
+            (None, Some(userinfo)) => (userinfo.sub, userinfo.extra),
+            (None, None) => return Err(IdTokenError::Missing.into()),
+        };
+        Ok(standard_user(Self::PROVIDER, sub, claims, token.into()))
+    }
+}
+
+/// 按OIDC标准声明填充用户信息
+///
+/// https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims
+pub(crate) fn standard_user(
+    provider: &str,
+    sub: String,
+    claims: HashMap<String, Value>,
+    token: AuthToken,
+) -> AuthUser {
+    let username = utils::get_str(&claims, "preferred_username");
+    AuthUser {
+        provider: provider.to_string(),
+        user_id: sub,
+        name: utils::get_str(&claims, "name")
+            .or_else(|| username.clone())
+            .or_else(|| utils::get_str(&claims, "nickname"))
+            .unwrap_or_default(),
+        username,
+        email: utils::get_str(&claims, "email"),
+        email_verified: match claims.get("email_verified") {
+            Some(Value::Bool(verified)) => Some(*verified),
+            Some(Value::String(verified)) => verified.parse().ok(),
+            _ => None,
+        },
+        avatar: utils::get_str(&claims, "picture"),
+        gender: match utils::get_str(&claims, "gender").as_deref() {
+            Some("male") => Some(Gender::Male),
+            Some("female") => Some(Gender::Female),
+            _ => None,
+        },
+        locale: utils::get_str(&claims, "locale"),
+        location: claims
+            .get("address")
+            .and_then(|address| address.get("formatted"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        profile_url: utils::get_str(&claims, "profile"),
+        token: Some(token),
+        extra: claims,
+        ..Default::default()
+    }
+}
+
+/// 校验id_token的签名和声明，签名公钥从jwks_uri获取并缓存，
+/// 遇到未知的kid时重新获取，以支持密钥轮换
+pub struct IdTokenVerifier {
+    provider: &'static str,
+    jwks_uri: String,
+    client_id: String,
+    issuers: Vec<String>,
+    leeway: Duration,
+    jwks: Mutex<CachedJwks>,
+}
+
+#[derive(Default)]
+struct CachedJwks {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+}
+
+/// [`IdTokenVerifier::leeway`]的默认值
+pub const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
+/// 两次获取jwks的最小间隔，避免伪造的kid导致频繁请求
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 只允许非对称签名算法
+const ALLOWED_ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+impl IdTokenVerifier {
+    pub fn new<U: Into<String>, C: Into<String>>(
+        provider: &'static str,
+        jwks_uri: U,
+        client_id: C,
+    ) -> Self {
+        Self {
+            provider,
+            jwks_uri: jwks_uri.into(),
+            client_id: client_id.into(),
+            issuers: Vec::new(),
+            leeway: DEFAULT_LEEWAY,
+            jwks: Mutex::new(CachedJwks::default()),
+        }
+    }
+
+    /// 允许的iss，可多次调用。不设置时不校验iss，由调用方自行校验，如多租户的微软账号
+    pub fn issuer<S: Into<String>>(mut self, issuer: S) -> Self {
+        self.issuers.push(issuer.into());
+        self
+    }
+
+    /// 校验id_token的exp、nbf时允许的时钟偏差，默认为[`DEFAULT_LEEWAY`]
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// 校验签名、iss、aud、exp，传入`nonce`时同时校验nonce
+    pub async fn verify(
+        &self,
+        http_client: &reqwest::Client,
+        id_token: &str,
+        nonce: Option<&str>,
+    ) -> Result<IdTokenClaims> {
+        let header = jsonwebtoken::decode_header(id_token).map_err(IdTokenError::Jwt)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(IdTokenError::UnsupportedAlgorithm(header.alg).into());
+        }
+        let jwk = self.find_key(http_client, header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(IdTokenError::Jwt)?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = self.leeway.as_secs();
+        if !self.issuers.is_empty() {
+            validation.set_issuer(&self.issuers);
+        }
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(IdTokenError::Jwt)?
+            .claims;
+        if claims.aud.len() > 1
+            && claims
+                .azp
+                .as_ref()
+                .is_some_and(|azp| azp != &self.client_id)
+        {
+            return Err(IdTokenError::ClaimMismatch("azp").into());
+        }
+        if nonce.is_some() && claims.nonce.as_deref() != nonce {
+            return Err(IdTokenError::ClaimMismatch("nonce").into());
+        }
+        Ok(claims)
+    }
+
+    async fn find_key(&self, http_client: &reqwest::Client, kid: Option<&str>) -> Result<Jwk> {
+        let fetched_at = {
+            let jwks = self.jwks.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(jwk) = select_key(&jwks.keys, kid) {
+                return Ok(jwk);
+            }
+            jwks.fetched_at
+        };
+        if fetched_at.is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH_INTERVAL) {
+            return Err(IdTokenError::UnknownKey(kid.map(str::to_string)).into());
+        }
+        let response = http_client.get(&self.jwks_uri).send().await?;
+        let jwk_set: JwkSet =
+            utils::parse_response::<ErrorResponse, _>(self.provider, response).await?;
+        let mut jwks = self.jwks.lock().unwrap_or_else(|e| e.into_inner());
+        jwks.keys = jwk_set.keys;
+        jwks.fetched_at = Some(Instant::now());
+        select_key(&jwks.keys, kid)
+            .ok_or_else(|| IdTokenError::UnknownKey(kid.map(str::to_string)).into())
+    }
+}
+
+/// 没有kid时只有一个公钥才能确定签名所用的公钥
+fn select_key(keys: &[Jwk], kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys
+            .iter()
+            .find(|jwk| jwk.common.key_id.as_deref() == Some(kid))
+            .cloned(),
+        None if keys.len() == 1 => keys.first().cloned(),
+        None => None,
+    }
+}
+
+/// https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+    pub revocation_endpoint: Option<String>,
+    pub end_session_endpoint: Option<String>,
+    #[serde(default)]
+    pub scopes_supported: Vec<String>,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+enum ClientAuthMethod {
+    Basic,
+    Post,
+}
+
+impl ProviderMetadata {
+    fn supports_pkce(&self) -> bool {
+        self.code_challenge_methods_supported
+            .iter()
+            .any(|method| method == PkceCodeChallengeMethod::S256.as_str())
+    }
+
+    /// 未声明时默认为client_secret_basic
+    fn client_auth_method(&self) -> ClientAuthMethod {
+        let methods = &self.token_endpoint_auth_methods_supported;
+        if !methods.is_empty()
+            && !methods.iter().any(|m| m == "client_secret_basic")
+            && methods.iter().any(|m| m == "client_secret_post")
+        {
+            ClientAuthMethod::Post
+        } else {
+            ClientAuthMethod::Basic
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    #[serde_as(as = "StringWithSeparator::<SpaceSeparator, String>")]
+    scope: Vec<String>,
+    state: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GetTokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<String>,
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    refresh_token: String,
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<Secret>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub token_type: Option<String>,
+    /// 部分服务端返回字符串
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    pub refresh_token: Option<Secret>,
+    /// Keycloak返回refresh_token的有效期
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(default)]
+    pub refresh_expires_in: Option<i64>,
+    pub id_token: Option<Secret>,
+    pub scope: Option<String>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: token.token_type,
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope.unwrap_or_default()),
+            id_token: token.id_token,
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+        .with_refresh_expires_in(token.refresh_expires_in.unwrap_or_default())
+    }
+}
+
+/// id_token中的声明，除以下字段外的声明如email、name在`extra`中
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    #[serde_as(as = "OneOrMany<_>")]
+    pub aud: Vec<String>,
+    pub exp: i64,
+    pub iat: Option<i64>,
+    pub nonce: Option<String>,
+    pub azp: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// https://openid.net/specs/openid-connect-core-1_0.html#UserInfoResponse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc6749#section-5.2
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        self.error
+            .map(|error| (error, self.error_description.unwrap_or_default()))
+    }
+}
@@ -0,0 +1,115 @@
+//! https://datatracker.ietf.org/doc/html/rfc7636
+use crate::secret::Secret;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// PKCE的code_verifier，授权时生成，需要由调用方保存到授权回调时提交
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PkceCodeVerifier(Secret);
+
+impl PkceCodeVerifier {
+    /// 32字节随机数的base64url编码，共43个字符
+    pub fn new_random() -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(Secret::new(URL_SAFE_NO_PAD.encode(bytes)))
+    }
+
+    /// 从保存的code_verifier恢复
+    pub fn new<S: Into<String>>(code_verifier: S) -> Self {
+        Self(Secret::new(code_verifier))
+    }
+
+    pub fn secret(&self) -> &str {
+        self.0.secret()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PkceCodeChallengeMethod {
+    S256,
+    #[serde(rename = "plain")]
+    Plain,
+}
+
+impl PkceCodeChallengeMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::S256 => "S256",
+            Self::Plain => "plain",
+        }
+    }
+}
+
+/// PKCE的code_challenge，放在授权url中
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PkceCodeChallenge {
+    code_challenge: String,
+    code_challenge_method: PkceCodeChallengeMethod,
+}
+
+impl PkceCodeChallenge {
+    /// 生成随机的code_verifier和对应的S256 code_challenge
+    pub fn new_random_s256() -> (Self, PkceCodeVerifier) {
+        let code_verifier = PkceCodeVerifier::new_random();
+        (Self::from_code_verifier_s256(&code_verifier), code_verifier)
+    }
+
+    /// code_challenge = BASE64URL(SHA256(code_verifier))
+    pub fn from_code_verifier_s256(code_verifier: &PkceCodeVerifier) -> Self {
+        let digest = Sha256::digest(code_verifier.secret().as_bytes());
+        Self {
+            code_challenge: URL_SAFE_NO_PAD.encode(digest),
+            code_challenge_method: PkceCodeChallengeMethod::S256,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.code_challenge
+    }
+
+    pub fn method(&self) -> PkceCodeChallengeMethod {
+        self.code_challenge_method
+    }
+}
+
+/// 支持PKCE的平台共用的授权回调参数
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PkceAuthCallback {
+    pub(crate) code: String,
+    pub(crate) state: Option<String>,
+    /// 授权时生成的code_verifier，不在回调参数中
+    #[serde(skip)]
+    pub(crate) code_verifier: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 7636 附录B中的示例
+    #[test]
+    fn s256_challenge() {
+        let code_verifier = PkceCodeVerifier::new("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        let challenge = PkceCodeChallenge::from_code_verifier_s256(&code_verifier);
+        assert_eq!(
+            challenge.as_str(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        assert_eq!(challenge.method().as_str(), "S256");
+    }
+
+    #[test]
+    fn random_verifier() {
+        let (challenge, code_verifier) = PkceCodeChallenge::new_random_s256();
+        assert_eq!(code_verifier.secret().len(), 43);
+        assert_eq!(
+            challenge,
+            PkceCodeChallenge::from_code_verifier_s256(&code_verifier)
+        );
+        assert_ne!(PkceCodeVerifier::new_random(), code_verifier);
+    }
+}
@@ -1,42 +1,70 @@
 //! https://wikinew.open.qq.com/index.html#/iwiki/901251864
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
 use crate::{
-    auth_server_builder, error::Result, utils, AuthAction, AuthConfig, AuthUrlProvider, AuthUser,
-    GenericAuthAction,
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Gender, GenericAuthAction,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use serde_with::{formats::CommaSeparator, serde_as, StringWithSeparator};
+use serde_with::{
+    formats::CommaSeparator, serde_as, DisplayFromStr, PickFirst, StringWithSeparator,
+};
 use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
 }
 
 auth_server_builder!();
 
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub open_id_url: String,
+    pub user_info_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://graph.qq.com/oauth2.0/authorize".to_string(),
+            access_token_url: "https://graph.qq.com/oauth2.0/token".to_string(),
+            open_id_url: "https://graph.qq.com/oauth2.0/me".to_string(),
+            user_info_url: "https://graph.qq.com/user/get_user_info".to_string(),
+        }
+    }
+}
+
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
     type TokenRequest = GetTokenRequest;
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://graph.qq.com/oauth2.0/authorize?response_type=token&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://graph.qq.com/oauth2.0/token?grant_type=authorization_code&{query}"
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String> {
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!("https://graph.qq.com/user/get_user_info?{query}"))
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
@@ -49,46 +77,77 @@ impl AuthAction for AuthorizationServer {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
         let AuthConfig {
             client_id,
-            client_secret,
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let access_token_url = self.access_token_url(GetTokenRequest {
             client_id: client_id.to_string(),
-            client_secret: client_secret.clone().expect("client_secret is empty"),
+            client_secret: self.config.client_secret()?,
             code: callback.code,
             redirect_uri: redirect_uri.to_string(),
             fmt: Some(ResponseFormat::Json),
         })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
         let AuthConfig { client_id, .. } = &self.config;
         let access_token = token.access_token;
-        let value = self.get_open_id(&access_token).await?;
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
-            openid: value.openid,
-            access_token: access_token,
+        let open_id = self.get_open_id(access_token.secret()).await?;
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
+            openid: open_id.openid.clone(),
+            access_token,
             oauth_consumer_key: client_id.to_string(),
         })?;
-        Ok(reqwest::get(user_info_url).await?.json().await?)
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        let user: UserInfoResponse =
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+        Ok(UserInfoResponse {
+            openid: open_id.openid,
+            ..user
+        })
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let AuthConfig { client_id, .. } = &self.config;
+        let refresh_token_url = self.refresh_token_url(RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            client_id: client_id.to_string(),
+            client_secret: self.config.client_secret()?,
+            refresh_token: refresh_token.into(),
+            fmt: Some(ResponseFormat::Json),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(refresh_token_url)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "qq";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: state.into(),
+            state,
             scope: scope.clone().or_else(|| Some(vec!["get_user_info".into()])),
             ..Default::default()
         })
@@ -96,38 +155,65 @@ impl GenericAuthAction for AuthorizationServer {
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
-        let AuthConfig { client_id, .. } = &self.config;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
-        let access_token = token.access_token;
-        let open_id = self.get_open_id(&access_token).await?;
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
-            openid: open_id.openid.clone(),
-            access_token: access_token.clone(),
-            oauth_consumer_key: client_id.to_string(),
-        })?;
-        let user: UserInfoResponse = reqwest::get(user_info_url).await?.json().await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    fn refresh_token_url(&self, request: RefreshTokenRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.access_token_url))
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        let location = ["province", "city"]
+            .into_iter()
+            .filter_map(|key| utils::get_str(&user.extra, key))
+            .collect::<Vec<_>>()
+            .join(" ");
         Ok(AuthUser {
-            user_id: open_id.openid,
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.openid,
             name: user.nickname,
-            access_token: access_token,
-            refresh_token: token.refresh_token,
-            expires_in: token.expires_in.into(),
+            avatar: utils::get_str(&user.extra, "figureurl_qq_2")
+                .or_else(|| utils::get_str(&user.extra, "figureurl_qq_1")),
+            gender: match utils::get_str(&user.extra, "gender").as_deref() {
+                Some("男") => Some(Gender::Male),
+                Some("女") => Some(Gender::Female),
+                _ => None,
+            },
+            location: Some(location).filter(|l| !l.is_empty()),
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
-}
 
-impl AuthorizationServer {
     async fn get_open_id(&self, access_token: &str) -> Result<OpenIdResp> {
-        let jsonp = reqwest::get(format!(
-            "https://graph.qq.com/oauth2.0/me?access_token={access_token}"
-        ))
-        .await?
-        .text()
-        .await?;
-        let json =
-            utils::substr_between(&jsonp, "callback(", ");").expect("jsonp response is valid");
-        Ok(serde_json::from_str(json)?)
+        let response = self
+            .config
+            .http_client
+            .get(&self.endpoints.open_id_url)
+            .query(&[("access_token", access_token)])
+            .send()
+            .await?;
+        let status = response.status();
+        let jsonp = response.text().await?;
+        let json = utils::substr_between(&jsonp, "callback(", ");").ok_or_else(|| {
+            AuthError::InvalidResponse {
+                provider: Self::PROVIDER,
+                message: format!("invalid jsonp response: {jsonp}"),
+            }
+        })?;
+        utils::parse_body::<ErrorResponse, _>(Self::PROVIDER, status, json)
     }
 }
 
@@ -152,13 +238,13 @@ pub enum QQDisplayStyle {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthCallback {
     code: String,
-    state: String,
+    state: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetTokenRequest {
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     code: String,
     redirect_uri: String,
     fmt: Option<ResponseFormat>,
@@ -176,16 +262,29 @@ pub enum ResponseFormat {
 pub struct RefreshTokenRequest {
     grant_type: String,
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     refresh_token: String,
     fmt: Option<ResponseFormat>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenResponse {
-    access_token: String,
+    access_token: Secret,
+    /// json格式返回的expires_in为字符串
+    #[serde_as(as = "PickFirst<(_, DisplayFromStr)>")]
     expires_in: i32,
-    refresh_token: String,
+    refresh_token: Secret,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            refresh_token: Some(token.refresh_token),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.into())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -196,7 +295,7 @@ pub struct OpenIdResp {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {
-    access_token: String,
+    access_token: Secret,
     oauth_consumer_key: String,
     openid: String,
 }
@@ -205,7 +304,34 @@ pub struct GetUserInfoRequest {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserInfoResponse {
+    /// 用户信息接口不返回openid，取自`/oauth2.0/me`
+    #[serde(default)]
+    pub openid: String,
     pub nickname: String,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// 获取token和openid失败时返回error字段，调用OpenAPI返回的ret不为0时表示失败
+///
+/// https://wiki.connect.qq.com/公共返回码说明
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<i64>,
+    error_description: Option<String>,
+    ret: Option<i64>,
+    msg: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.ret) {
+            (Some(error), _) => Some((
+                error.to_string(),
+                self.error_description.unwrap_or_default(),
+            )),
+            (None, Some(ret)) if ret != 0 => Some((ret.to_string(), self.msg.unwrap_or_default())),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! 按平台名称注册多个[`AuthProvider`]，一个回调路由即可分发至所有已配置的平台
+use crate::error::{AuthError, Result};
+use crate::{AuthProvider, AuthUser};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub struct ProviderRegistry {
+    providers: HashMap<String, Arc<dyn AuthProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以平台默认名称注册，如`github`、`qq`
+    pub fn register<P: AuthProvider + 'static>(self, provider: P) -> Self {
+        let name = provider.provider();
+        self.register_as(name, provider)
+    }
+
+    /// 以自定义名称注册，用于同一平台配置多个应用的场景
+    pub fn register_as<S: Into<String>, P: AuthProvider + 'static>(
+        mut self,
+        name: S,
+        provider: P,
+    ) -> Self {
+        self.providers.insert(name.into(), Arc::new(provider));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<dyn AuthProvider>> {
+        self.providers
+            .get(name)
+            .cloned()
+            .ok_or_else(|| AuthError::UnknownProvider(name.to_string()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.providers.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.providers.keys().map(String::as_str)
+    }
+
+    pub async fn authorize<S: Into<String>>(&self, name: &str, state: S) -> Result<String> {
+        self.get(name)?.authorize(state.into()).await
+    }
+
+    /// 生成随机的state，返回`(授权url, state)`
+    pub async fn authorize_with_random_state(&self, name: &str) -> Result<(String, String)> {
+        self.get(name)?.authorize_with_random_state().await
+    }
+
+    pub async fn login<S: Into<String>>(
+        &self,
+        name: &str,
+        callback_raw_query: S,
+    ) -> Result<AuthUser> {
+        self.get(name)?.login(callback_raw_query.into()).await
+    }
+
+    pub async fn refresh<S: Into<String>>(&self, name: &str, refresh_token: S) -> Result<AuthUser> {
+        self.get(name)?.refresh(refresh_token.into()).await
+    }
+
+    pub async fn revoke<S: Into<String>>(&self, name: &str, token: S) -> Result<()> {
+        self.get(name)?.revoke(token.into()).await
+    }
+}
@@ -0,0 +1,62 @@
+//! 敏感信息的包装类型，避免被日志打印或残留在内存中
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
+
+/// client_secret、token等敏感信息，`Debug`不会输出内容，drop时清零内存
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new<S: Into<String>>(secret: S) -> Self {
+        Self(secret.into())
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacted() {
+        let secret = Secret::new("client-secret");
+        assert_eq!(format!("{secret:?}"), "Secret([REDACTED])");
+        assert_eq!(secret.secret(), "client-secret");
+    }
+
+    #[test]
+    fn serde_transparent() {
+        let secret: Secret = serde_json::from_str(r#""token""#).unwrap();
+        assert_eq!(secret.secret(), "token");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""token""#);
+    }
+}
@@ -0,0 +1,180 @@
+//! 授权state的生成与校验
+//!
+//! `authorize`将state按平台保存到[`StateStore`]，`login`校验回调中的state，
+//! 缺失、未知、过期或重复使用的state会返回[`AuthError::InvalidState`]。
+//! 未配置时使用[`MemoryStateStore`]。
+//!
+//! StateStore中的state不区分浏览器，防止CSRF攻击还需要把state绑定到发起授权的浏览器会话：
+//! 调用方将`authorize_with_random_state`返回的state保存在cookie等会话中，
+//! 回调时通过`login_with_state`与回调中的state比对。
+//! [`generate`]生成不可猜测的state，`authorize_with_random_state`会自动调用
+use crate::error::{AuthError, Result, StateError};
+use crate::pkce::PkceCodeVerifier;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 生成32字节随机数的base64url编码作为state
+pub fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// 比对回调中的state与浏览器会话中保存的state，比较时间与内容无关
+pub(crate) fn check_callback(callback_raw_query: &str, expected_state: &str) -> Result<()> {
+    let state = url::form_urlencoded::parse(callback_raw_query.as_bytes())
+        .find(|(key, _)| key == "state")
+        .map(|(_, value)| value)
+        .ok_or(AuthError::InvalidState(StateError::Missing))?;
+    let matched = state.len() == expected_state.len()
+        && state
+            .bytes()
+            .zip(expected_state.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if !matched {
+        return Err(AuthError::InvalidState(StateError::Mismatch));
+    }
+    Ok(())
+}
+
+/// 和state一起保存，授权回调时取回的数据
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateData {
+    pub code_verifier: Option<PkceCodeVerifier>,
+    /// OpenID Connect授权请求中的nonce，用于校验id_token
+    pub nonce: Option<String>,
+}
+
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// 保存授权时生成的state
+    async fn save(&self, state: &str, data: StateData) -> Result<()>;
+
+    /// 取出并作废state，state未知、过期或已被使用时返回[`AuthError::InvalidState`]
+    async fn consume(&self, state: &str) -> Result<StateData>;
+}
+
+/// 基于内存的[`StateStore`]，state在ttl后过期，只适用于单实例部署
+pub struct MemoryStateStore {
+    ttl: Duration,
+    states: Mutex<HashMap<String, StateEntry>>,
+}
+
+struct StateEntry {
+    expires_at: Instant,
+    /// 已使用的state保留到过期，用于识别重复使用
+    data: Option<StateData>,
+}
+
+impl MemoryStateStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for MemoryStateStore {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(600))
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryStateStore {
+    async fn save(&self, state: &str, data: StateData) -> Result<()> {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        states.retain(|_, entry| entry.expires_at > now);
+        states.insert(
+            state.to_string(),
+            StateEntry {
+                expires_at: now + self.ttl,
+                data: Some(data),
+            },
+        );
+        Ok(())
+    }
+
+    async fn consume(&self, state: &str) -> Result<StateData> {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = states
+            .get_mut(state)
+            .ok_or(AuthError::InvalidState(StateError::Unknown))?;
+        if entry.expires_at <= Instant::now() {
+            states.remove(state);
+            return Err(AuthError::InvalidState(StateError::Expired));
+        }
+        entry
+            .data
+            .take()
+            .ok_or(AuthError::InvalidState(StateError::Reused))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn consume_once() {
+        let store = MemoryStateStore::default();
+        let data = StateData {
+            code_verifier: Some(PkceCodeVerifier::new("code-verifier")),
+            nonce: Some("nonce".into()),
+        };
+        store.save("state", data.clone()).await.unwrap();
+        assert_eq!(store.consume("state").await.unwrap(), data);
+        assert!(matches!(
+            store.consume("state").await,
+            Err(AuthError::InvalidState(StateError::Reused))
+        ));
+        assert!(matches!(
+            store.consume("unknown").await,
+            Err(AuthError::InvalidState(StateError::Unknown))
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired() {
+        let store = MemoryStateStore::new(Duration::from_millis(10));
+        store.save("state", StateData::default()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(matches!(
+            store.consume("state").await,
+            Err(AuthError::InvalidState(StateError::Expired))
+        ));
+        // 过期的state会被移除
+        assert!(matches!(
+            store.consume("state").await,
+            Err(AuthError::InvalidState(StateError::Unknown))
+        ));
+    }
+
+    #[test]
+    fn check_callback_state() {
+        check_callback("code=code&state=abc", "abc").unwrap();
+        assert!(matches!(
+            check_callback("code=code&state=abd", "abc"),
+            Err(AuthError::InvalidState(StateError::Mismatch))
+        ));
+        assert!(matches!(
+            check_callback("code=code", "abc"),
+            Err(AuthError::InvalidState(StateError::Missing))
+        ));
+    }
+
+    #[test]
+    fn generate_unique() {
+        let state = generate();
+        assert_eq!(state.len(), 43);
+        assert_ne!(state, generate());
+    }
+}
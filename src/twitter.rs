@@ -1,9 +1,15 @@
 //! https://developer.x.com/en/docs/authentication/oauth-2-0/authorization-code
 //! https://developer.x.com/en/docs/authentication/oauth-2-0/user-access-token
 //! https://developer.x.com/en/docs/x-api/users/lookup/api-reference/get-users-me
-use crate::error::Result;
+//!
+//! X要求使用PKCE，code_verifier默认随state保存到StateStore，也可以通过[`GenericAuthAction::authorize_with_pkce`]和
+//! [`GenericAuthAction::login_with_pkce`]完成登录
+use crate::error::{AuthError, Result};
+use crate::pkce::{PkceAuthCallback, PkceCodeChallenge, PkceCodeVerifier};
+use crate::secret::Secret;
 use crate::{
-    auth_server_builder, AuthAction, AuthConfig, AuthUrlProvider, AuthUser, GenericAuthAction,
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthToken, AuthUrlProvider, AuthUser,
+    Authorization, GenericAuthAction,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -16,37 +22,58 @@ use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
 }
 
-auth_server_builder!();
+auth_server_builder!(public_client);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub revoke_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://x.com/i/oauth2/authorize".to_string(),
+            access_token_url: "https://api.x.com/2/oauth2/token".to_string(),
+            user_info_url: "https://api.x.com/2/users/me".to_string(),
+            revoke_url: "https://api.x.com/2/oauth2/revoke".to_string(),
+        }
+    }
+}
 
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
     type TokenRequest = GetTokenRequest;
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://twitter.com/i/oauth2/authorize?response_type=code&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
-        let query = serde_urlencoded::to_string(request)?;
-        Ok(format!(
-            "https://api.x.com/2/oauth2/token?grant_type=authorization_code&{query}"
-        ))
+    /// 获取accessToken需要以POST表单提交请求参数
+    fn access_token_url(&self, _request: Self::TokenRequest) -> Result<String> {
+        Ok(self.endpoints.access_token_url.clone())
     }
 
-    fn user_info_url(_request: Self::UserInfoRequest) -> Result<String> {
-        Ok(format!("https://api.x.com/2/users/me"))
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
 #[async_trait]
 impl AuthAction for AuthorizationServer {
-    type AuthCallback = AuthCallback;
+    type AuthCallback = PkceAuthCallback;
     type AuthToken = TokenResponse;
     type AuthUser = UserInfoResponse;
 
@@ -56,17 +83,25 @@ impl AuthAction for AuthorizationServer {
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let request = GetTokenRequest {
+            grant_type: "authorization_code".to_string(),
             client_id: client_id.to_string(),
             code: callback.code,
             redirect_uri: redirect_uri.to_string(),
-            code_verifier: "aaa".to_string(),
-        })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+            code_verifier: callback
+                .code_verifier
+                .ok_or(AuthError::MissingCodeVerifier)?,
+        };
+        let access_token_url = self.access_token_url(request.clone())?;
+        let response = self
+            .token_request(access_token_url, &request)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
             user_fields: [
                 "created_at",
                 "description",
@@ -89,48 +124,157 @@ impl AuthAction for AuthorizationServer {
             .to_vec(),
             ..Default::default()
         })?;
-        Ok(reqwest::Client::default()
+        let response = self
+            .config
+            .http_client
             .get(user_info_url)
-            .bearer_auth(token.access_token)
+            .bearer_auth(token.access_token.secret())
+            .send()
+            .await?;
+        let user: UserInfoData =
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+        Ok(user.data)
+    }
+
+    /// 授权时scope需要包含`offline.access`才会返回refresh_token
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let AuthConfig { client_id, .. } = &self.config;
+        let request = RefreshTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.into(),
+            client_id: client_id.to_string(),
+        };
+        let response = self
+            .token_request(&self.endpoints.access_token_url, &request)
             .send()
-            .await?
-            .json()
-            .await?)
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "twitter";
+
+    /// 自动生成PKCE，code_verifier随state一起保存
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        let authorization = self.config.begin_authorization(&state, true, false).await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let (callback, _) = self
+            .config
+            .verify_pkce_callback(&callback.into(), None)
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn authorize_with_pkce<S: Into<String> + Send>(
+        &self,
+        state: S,
+        code_challenge: &PkceCodeChallenge,
+    ) -> Result<String> {
+        let state = state.into();
+        let authorization = self
+            .config
+            .begin_authorization_with_pkce(&state, code_challenge, false)
+            .await?;
+        self.build_authorize_url(state, authorization)
+    }
+
+    async fn login_with_pkce<S: Into<String> + Send>(
+        &self,
+        callback: S,
+        code_verifier: &PkceCodeVerifier,
+    ) -> Result<AuthUser> {
+        let (callback, _) = self
+            .config
+            .verify_pkce_callback(&callback.into(), Some(code_verifier))
+            .await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+
+    /// 撤销accessToken或refreshToken
+    async fn revoke<S: Into<String> + Send>(&self, token: S) -> Result<()> {
+        let token = token.into();
+        let request = utils::RevokeTokenRequest {
+            token: &token,
+            token_type_hint: None,
+            client_id: Some(&self.config.client_id),
+            client_secret: None,
+        };
+        let response = self
+            .token_request(&self.endpoints.revoke_url, &request)
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+}
+
+impl AuthorizationServer {
+    fn build_authorize_url(&self, state: String, authorization: Authorization) -> Result<String> {
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: state.into(),
+            state,
             scope: scope
                 .clone()
-                .or_else(|| Some(vec!["tweet.read".into(), "users.read".into()]))
-                .expect("scope is empty"),
-            ..Default::default()
+                .unwrap_or_else(|| vec!["tweet.read".into(), "users.read".into()]),
+            code_challenge: authorization.code_challenge(),
+            code_challenge_method: authorization.code_challenge_method(),
         })
     }
 
-    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
-        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
-        let token = self.get_access_token(callback).await?;
+    /// 机密客户端(配置了client_secret)需要使用Basic认证
+    fn token_request<U: reqwest::IntoUrl, T: Serialize>(
+        &self,
+        url: U,
+        form: &T,
+    ) -> reqwest::RequestBuilder {
+        let AuthConfig {
+            client_id,
+            client_secret,
+            ..
+        } = &self.config;
+        let request = self.config.http_client.post(url).form(form);
+        match client_secret {
+            Some(client_secret) => request.basic_auth(client_id, Some(client_secret.secret())),
+            None => request,
+        }
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
         let user = self.get_user_info(token.clone()).await?;
+        let username = utils::get_str(&user.extra, "username");
         Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
             user_id: user.id,
             name: user.name,
-            access_token: token.access_token,
-            refresh_token: token.token_type,
-            expires_in: i64::MAX,
+            avatar: utils::get_str(&user.extra, "profile_image_url"),
+            location: utils::get_str(&user.extra, "location"),
+            profile_url: username.as_ref().map(|u| format!("https://x.com/{u}")),
+            username,
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
 }
@@ -148,32 +292,48 @@ pub struct AuthRequest {
     code_challenge_method: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthCallback {
-    code: String,
-    state: String,
-}
-
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct GetTokenRequest {
+    grant_type: String,
     client_id: String,
     code: String,
     redirect_uri: String,
     code_verifier: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenRequest {
+    grant_type: String,
+    refresh_token: String,
+    client_id: String,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenResponse {
-    pub access_token: String,
+    pub access_token: Secret,
     pub scope: String,
     pub token_type: String,
+    pub expires_in: Option<i64>,
+    pub refresh_token: Option<Secret>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            token_type: Some(token.token_type),
+            refresh_token: token.refresh_token,
+            scopes: utils::split_scopes(&token.scope),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in.unwrap_or_default())
+    }
 }
 
 #[serde_as]
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {
     expansions: Option<String>,
-    #[serde(rename = "tweet.fields")]
+    #[serde(rename = "tweet.fields", skip_serializing_if = "Vec::is_empty")]
     #[serde_as(as = "StringWithSeparator::<CommaSeparator, String>")]
     tweet_fields: Vec<String>,
     #[serde(rename = "user.fields")]
@@ -182,6 +342,11 @@ pub struct GetUserInfoRequest {
 }
 
 /// https://developer.x.com/en/docs/x-api/users/lookup/api-reference/get-users-me
+#[derive(Debug, Deserialize)]
+pub struct UserInfoData {
+    data: UserInfoResponse,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UserInfoResponse {
     pub id: String,
@@ -189,3 +354,24 @@ pub struct UserInfoResponse {
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// 获取accessToken失败时返回error字段，API请求失败时返回title和detail
+///
+/// https://developer.x.com/en/support/x-api/error-troubleshooting
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_description: Option<String>,
+    title: Option<String>,
+    detail: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error, self.title) {
+            (Some(error), _) => Some((error, self.error_description.unwrap_or_default())),
+            (None, Some(title)) => Some((title, self.detail.unwrap_or_default())),
+            (None, None) => None,
+        }
+    }
+}
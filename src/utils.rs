@@ -1,3 +1,10 @@
+use crate::error::{AuthError, Result};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
 pub fn substr_between<'a>(s: &'a str, prefix: &str, suffix: &str) -> Option<&'a str> {
     if let Some(start) = s.find(prefix) {
         let start_pos = start + prefix.len();
@@ -6,4 +13,98 @@ pub fn substr_between<'a>(s: &'a str, prefix: &str, suffix: &str) -> Option<&'a
         }
     }
     None
-}
\ No newline at end of file
+}
+
+/// 读取用户信息中的字符串字段，空字符串视为`None`
+pub(crate) fn get_str(extra: &HashMap<String, Value>, key: &str) -> Option<String> {
+    extra
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// 拆分平台返回的scope，各平台使用逗号或空格分隔
+pub(crate) fn split_scopes(scope: &str) -> Vec<String> {
+    scope
+        .split([',', ' '])
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// 平台的错误响应格式
+pub(crate) trait ErrorResponse: DeserializeOwned {
+    /// 响应为错误时返回错误码和错误信息，错误码为空时使用http状态码
+    fn into_error(self) -> Option<(String, String)>;
+}
+
+/// 先按平台的错误格式解析响应，不是错误响应再解析为`T`
+pub(crate) async fn parse_response<E: ErrorResponse, T: DeserializeOwned>(
+    provider: &'static str,
+    response: Response,
+) -> Result<T> {
+    let status = response.status();
+    let body = response.text().await?;
+    parse_body::<E, T>(provider, status, &body)
+}
+
+pub(crate) fn parse_body<E: ErrorResponse, T: DeserializeOwned>(
+    provider: &'static str,
+    status: StatusCode,
+    body: &str,
+) -> Result<T> {
+    check_body::<E>(provider, status, body)?;
+    Ok(serde_json::from_str(body)?)
+}
+
+/// 只检查是否为错误响应，用于撤销授权等不关心响应内容的接口
+pub(crate) async fn check_response<E: ErrorResponse>(
+    provider: &'static str,
+    response: Response,
+) -> Result<()> {
+    let status = response.status();
+    let body = response.text().await?;
+    check_body::<E>(provider, status, &body)
+}
+
+fn check_body<E: ErrorResponse>(
+    provider: &'static str,
+    status: StatusCode,
+    body: &str,
+) -> Result<()> {
+    let error = serde_json::from_str::<E>(body)
+        .ok()
+        .and_then(ErrorResponse::into_error);
+    let error = match error {
+        Some((code, message)) if code.is_empty() => Some((status.as_u16().to_string(), message)),
+        Some(error) => Some(error),
+        None if !status.is_success() => Some((status.as_u16().to_string(), body.to_string())),
+        None => None,
+    };
+    match error {
+        Some((code, message)) => Err(AuthError::Provider {
+            provider,
+            code,
+            message,
+            http_status: status.as_u16(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// RFC 7009撤销token的请求参数，token无效时服务端同样返回成功
+///
+/// https://datatracker.ietf.org/doc/html/rfc7009#section-2.1
+#[derive(Debug, Serialize)]
+pub(crate) struct RevokeTokenRequest<'a> {
+    pub token: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type_hint: Option<&'a str>,
+    /// 公共客户端没有client_secret，需要在参数中带上client_id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<&'a str>,
+    /// 不支持Basic认证的平台在参数中提交client_secret
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<&'a str>,
+}
@@ -0,0 +1,133 @@
+//! https://developers.weixin.qq.com/doc/oplatform/Website_App/WeChat_Login/Authorized_Interface_Calling_UnionID.html
+//! https://developers.weixin.qq.com/doc/offiaccount/OA_Web_Apps/Wechat_webpage_authorization.html
+//!
+//! 微信开放平台和公众号网页授权共用的`sns`接口类型
+use crate::error::Result;
+use crate::secret::Secret;
+use crate::{utils, AuthToken, AuthUser, Gender};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCallback {
+    pub(crate) code: String,
+    pub(crate) state: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetTokenRequest {
+    pub(crate) appid: String,
+    pub(crate) secret: Secret,
+    pub(crate) code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub(crate) grant_type: String,
+    pub(crate) appid: String,
+    pub(crate) refresh_token: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Secret,
+    pub expires_in: i64,
+    pub refresh_token: Secret,
+    pub openid: String,
+    pub scope: String,
+    /// 刷新accessToken的响应和未绑定开放平台的应用没有unionid
+    pub unionid: Option<String>,
+}
+
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken {
+            refresh_token: Some(token.refresh_token),
+            scopes: utils::split_scopes(&token.scope),
+            ..AuthToken::new(token.access_token)
+        }
+        .with_expires_in(token.expires_in)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {
+    pub(crate) access_token: Secret,
+    pub(crate) openid: String,
+    pub(crate) lang: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub openid: String,
+    /// 公众号或应用绑定到开放平台后才有unionid
+    pub unionid: Option<String>,
+    pub nickname: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// https://developers.weixin.qq.com/doc/oplatform/Return_codes/Return_code_descriptions_new.html
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    errcode: Option<i64>,
+    errmsg: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match self.errcode {
+            Some(errcode) if errcode != 0 => {
+                Some((errcode.to_string(), self.errmsg.unwrap_or_default()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 刷新accessToken的请求地址，开放平台和公众号的参数相同
+pub(crate) fn refresh_token_url(
+    refresh_token_url: &str,
+    appid: &str,
+    refresh_token: String,
+) -> Result<String> {
+    let query = serde_urlencoded::to_string(RefreshTokenRequest {
+        grant_type: "refresh_token".to_string(),
+        appid: appid.to_string(),
+        refresh_token,
+    })?;
+    Ok(format!("{refresh_token_url}?{query}"))
+}
+
+/// 按`sns/userinfo`的字段填充昵称、头像、性别和地区
+pub(crate) fn auth_user(
+    provider: &str,
+    user_id: String,
+    user: UserInfoResponse,
+    token: AuthToken,
+) -> AuthUser {
+    let extra = user.extra;
+    let location = ["country", "province", "city"]
+        .into_iter()
+        .filter_map(|key| utils::get_str(&extra, key))
+        .collect::<Vec<_>>()
+        .join(" ");
+    AuthUser {
+        provider: provider.to_string(),
+        user_id,
+        name: user.nickname,
+        avatar: utils::get_str(&extra, "headimgurl"),
+        gender: match extra.get("sex").and_then(Value::as_i64) {
+            Some(1) => Some(Gender::Male),
+            Some(2) => Some(Gender::Female),
+            _ => None,
+        },
+        location: Some(location).filter(|l| !l.is_empty()),
+        union_id: user.unionid,
+        open_id: Some(user.openid),
+        token: Some(token),
+        extra,
+        ..Default::default()
+    }
+}
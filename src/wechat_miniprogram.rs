@@ -0,0 +1,414 @@
+//! 微信小程序登录
+//! https://developers.weixin.qq.com/miniprogram/dev/framework/open-ability/login.html
+//!
+//! 小程序没有授权页和回调，前端`wx.login`获取的code提交给后端换取openid和session_key，
+//! 因此不实现[`crate::GenericAuthAction`]，也不需要redirect_uri。
+//! `client_id`为小程序的AppID，`client_secret`为AppSecret。
+//! 用户信息和手机号等开放数据由前端加密传给后端，使用session_key解密
+use crate::credential::AccessTokenCache;
+use crate::error::{AuthError, Result, UserDataError};
+use crate::secret::Secret;
+use crate::{auth_server_builder, utils, wechat::ErrorResponse, AuthConfig};
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::future::Future;
+use subtle::ConstantTimeEq;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+}
+
+auth_server_builder!(without_redirect, options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub code_to_session_url: String,
+    /// 获取稳定版接口调用凭证
+    pub stable_token_url: String,
+    pub phone_number_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            code_to_session_url: "https://api.weixin.qq.com/sns/jscode2session".to_string(),
+            stable_token_url: "https://api.weixin.qq.com/cgi-bin/stable_token".to_string(),
+            phone_number_url: "https://api.weixin.qq.com/wxa/business/getuserphonenumber"
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 小程序access_token的缓存，多个`AuthorizationServer`可以共享同一个缓存，不同小程序分别缓存
+    pub access_token: AccessTokenCache,
+}
+
+/// access_token无效或过期的错误码
+///
+/// https://developers.weixin.qq.com/miniprogram/dev/OpenApiDoc/getaccesstoken.html
+const INVALID_ACCESS_TOKEN: [&str; 3] = ["40001", "40014", "42001"];
+
+impl AuthorizationServer {
+    pub const PROVIDER: &'static str = "wechat_miniprogram";
+
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+        })
+    }
+
+    /// 使用`wx.login`获取的code换取openid、unionid和session_key
+    ///
+    /// https://developers.weixin.qq.com/miniprogram/dev/OpenApiDoc/user-login/code2Session.html
+    pub async fn code_to_session<S: Into<String>>(&self, js_code: S) -> Result<SessionResponse> {
+        let query = serde_urlencoded::to_string(CodeToSessionRequest {
+            appid: self.config.client_id.to_string(),
+            secret: self.config.client_secret()?,
+            js_code: js_code.into(),
+            grant_type: "authorization_code".to_string(),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(format!("{}?{query}", self.endpoints.code_to_session_url))
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// 校验`wx.getUserInfo`返回的rawData，signature为`sha1(rawData + session_key)`
+    ///
+    /// https://developers.weixin.qq.com/miniprogram/dev/framework/open-ability/signature.html
+    pub fn verify_signature(
+        &self,
+        session_key: &str,
+        raw_data: &str,
+        signature: &str,
+    ) -> Result<()> {
+        let digest = Sha1::new()
+            .chain_update(raw_data.as_bytes())
+            .chain_update(session_key.as_bytes())
+            .finalize();
+        let signature = hex::decode(signature).map_err(|_| UserDataError::SignatureMismatch)?;
+        if !bool::from(digest.as_slice().ct_eq(&signature)) {
+            return Err(UserDataError::SignatureMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// 使用session_key解密开放数据，并校验水印中的appid为当前小程序。
+    /// 数据为AES-128-CBC加密，key为session_key，encrypted_data、session_key和iv都为base64编码
+    pub fn decrypt_data<T: DeserializeOwned>(
+        &self,
+        session_key: &str,
+        encrypted_data: &str,
+        iv: &str,
+    ) -> Result<T> {
+        let key = STANDARD.decode(session_key).map_err(UserDataError::from)?;
+        let iv = STANDARD.decode(iv).map_err(UserDataError::from)?;
+        let mut data = STANDARD
+            .decode(encrypted_data)
+            .map_err(UserDataError::from)?;
+        let plaintext = Aes128CbcDec::new_from_slices(&key, &iv)
+            .map_err(|_| UserDataError::InvalidKey)?
+            .decrypt_padded_mut::<Pkcs7>(&mut data)
+            .map_err(|_| UserDataError::Decrypt)?;
+        let watermarked: Watermarked = serde_json::from_slice(plaintext)?;
+        self.check_watermark(&watermarked.watermark)?;
+        Ok(serde_json::from_slice(plaintext)?)
+    }
+
+    /// 解密`wx.getUserInfo`返回的encryptedData
+    pub fn decrypt_user_info(
+        &self,
+        session_key: &str,
+        encrypted_data: &str,
+        iv: &str,
+    ) -> Result<UserInfo> {
+        self.decrypt_data(session_key, encrypted_data, iv)
+    }
+
+    /// 解密旧版手机号快速验证组件返回的encryptedData，新版组件返回code，使用[`Self::phone_number`]
+    pub fn decrypt_phone_number(
+        &self,
+        session_key: &str,
+        encrypted_data: &str,
+        iv: &str,
+    ) -> Result<PhoneInfo> {
+        self.decrypt_data(session_key, encrypted_data, iv)
+    }
+
+    /// 使用手机号快速验证组件返回的code获取手机号
+    ///
+    /// https://developers.weixin.qq.com/miniprogram/dev/OpenApiDoc/user-info/phone-number/getPhoneNumber.html
+    pub async fn phone_number<S: Into<String>>(&self, code: S) -> Result<PhoneInfo> {
+        let request = GetPhoneNumberRequest { code: code.into() };
+        let response: PhoneNumberResponse = self
+            .with_access_token(|access_token| async {
+                let query = serde_urlencoded::to_string([("access_token", access_token)])?;
+                let response = self
+                    .config
+                    .http_client
+                    .post(format!("{}?{query}", self.endpoints.phone_number_url))
+                    .json(&request)
+                    .send()
+                    .await?;
+                utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+            })
+            .await?;
+        self.check_watermark(&response.phone_info.watermark)?;
+        Ok(response.phone_info)
+    }
+
+    /// 获取缓存的稳定版access_token，过期时重新获取。
+    /// 稳定版接口与`cgi-bin/token`互不影响，多处获取不会使已有的access_token失效
+    ///
+    /// https://developers.weixin.qq.com/miniprogram/dev/OpenApiDoc/mp-access-token/getStableAccessToken.html
+    pub async fn access_token(&self) -> Result<String> {
+        self.options
+            .access_token
+            .get_or_fetch(
+                &self.endpoints.stable_token_url,
+                &self.config.client_id,
+                || async {
+                    let request = GetStableTokenRequest {
+                        grant_type: "client_credential".to_string(),
+                        appid: self.config.client_id.to_string(),
+                        secret: self.config.client_secret()?,
+                        force_refresh: false,
+                    };
+                    let response = self
+                        .config
+                        .http_client
+                        .post(&self.endpoints.stable_token_url)
+                        .json(&request)
+                        .send()
+                        .await?;
+                    let token: TokenResponse =
+                        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+                    Ok((token.access_token.secret().to_string(), token.expires_in))
+                },
+            )
+            .await
+    }
+
+    /// access_token被提前作废时清除缓存并重试一次
+    async fn with_access_token<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let access_token = self.access_token().await?;
+        match request(access_token).await {
+            Err(AuthError::Provider { code, .. })
+                if INVALID_ACCESS_TOKEN.contains(&code.as_str()) =>
+            {
+                self.options
+                    .access_token
+                    .invalidate(&self.endpoints.stable_token_url, &self.config.client_id)
+                    .await;
+                request(self.access_token().await?).await
+            }
+            result => result,
+        }
+    }
+
+    fn check_watermark(&self, watermark: &Watermark) -> Result<()> {
+        if watermark.appid != self.config.client_id {
+            return Err(UserDataError::WatermarkMismatch(watermark.appid.clone()).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CodeToSessionRequest {
+    appid: String,
+    secret: Secret,
+    js_code: String,
+    grant_type: String,
+}
+
+/// 小程序绑定到开放平台后才有unionid
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub openid: String,
+    pub session_key: Secret,
+    pub unionid: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// 开放数据中的水印，appid为数据所属的小程序
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watermark {
+    pub appid: String,
+    pub timestamp: i64,
+}
+
+#[derive(Deserialize)]
+struct Watermarked {
+    watermark: Watermark,
+}
+
+/// https://developers.weixin.qq.com/miniprogram/dev/api/open-api/user-info/UserInfo.html
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    pub open_id: String,
+    pub union_id: Option<String>,
+    pub nick_name: String,
+    /// 0未知，1男性，2女性
+    #[serde(default)]
+    pub gender: i64,
+    pub avatar_url: Option<String>,
+    pub country: Option<String>,
+    pub province: Option<String>,
+    pub city: Option<String>,
+    pub language: Option<String>,
+    pub watermark: Watermark,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhoneInfo {
+    /// 带区号的手机号，境外手机号会有区号
+    pub phone_number: String,
+    /// 不带区号的手机号
+    pub pure_phone_number: String,
+    pub country_code: String,
+    pub watermark: Watermark,
+}
+
+#[derive(Debug, Serialize)]
+struct GetPhoneNumberRequest {
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PhoneNumberResponse {
+    phone_info: PhoneInfo,
+}
+
+#[derive(Debug, Serialize)]
+struct GetStableTokenRequest {
+    grant_type: String,
+    appid: String,
+    secret: Secret,
+    force_refresh: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Secret,
+    expires_in: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+    const SESSION_KEY: &str = "tiihtNczf5v6AKRyjwEUhQ==";
+    const IV: &str = "r7BXXKkLb8qrSNn05n0qiA==";
+
+    fn server(appid: &str) -> AuthorizationServer {
+        AuthorizationServer::builder()
+            .client_id(appid)
+            .client_secret("secret")
+            .build()
+            .unwrap()
+    }
+
+    fn encrypt(plaintext: &str) -> String {
+        let key = STANDARD.decode(SESSION_KEY).unwrap();
+        let iv = STANDARD.decode(IV).unwrap();
+        let mut buf = plaintext.as_bytes().to_vec();
+        buf.resize(plaintext.len() + 16, 0);
+        let ciphertext = Aes128CbcEnc::new_from_slices(&key, &iv)
+            .unwrap()
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, plaintext.len())
+            .unwrap();
+        STANDARD.encode(ciphertext)
+    }
+
+    #[test]
+    fn decrypt_phone_number() {
+        let data = encrypt(
+            r#"{"phoneNumber":"13800000000","purePhoneNumber":"13800000000","countryCode":"86","watermark":{"appid":"wx-app","timestamp":1700000000}}"#,
+        );
+        let phone = server("wx-app")
+            .decrypt_phone_number(SESSION_KEY, &data, IV)
+            .unwrap();
+        assert_eq!(phone.pure_phone_number, "13800000000");
+        assert_eq!(phone.watermark.timestamp, 1700000000);
+
+        let error = server("other-app")
+            .decrypt_phone_number(SESSION_KEY, &data, IV)
+            .unwrap_err();
+        assert!(
+            matches!(&error, AuthError::InvalidUserData(UserDataError::WatermarkMismatch(appid)) if appid == "wx-app"),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn decrypt_with_wrong_key() {
+        let data = encrypt(
+            r#"{"openId":"openid","nickName":"mock","watermark":{"appid":"wx-app","timestamp":0}}"#,
+        );
+        let error = server("wx-app")
+            .decrypt_user_info("AAAAAAAAAAAAAAAAAAAAAA==", &data, IV)
+            .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                AuthError::InvalidUserData(UserDataError::Decrypt) | AuthError::JsonParseErr(_)
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn verify_signature() {
+        let server = server("wx-app");
+        let raw_data = r#"{"nickName":"mock"}"#;
+        let signature = format!(
+            "{:x}",
+            Sha1::digest(format!("{raw_data}{SESSION_KEY}").as_bytes())
+        );
+        server
+            .verify_signature(SESSION_KEY, raw_data, &signature)
+            .unwrap();
+        server
+            .verify_signature(SESSION_KEY, raw_data, &signature.to_uppercase())
+            .unwrap();
+        for (raw_data, signature) in [
+            (r#"{"nickName":"other"}"#, signature.as_str()),
+            (raw_data, &signature[2..]),
+            (raw_data, "not-hex"),
+        ] {
+            assert!(matches!(
+                server.verify_signature(SESSION_KEY, raw_data, signature),
+                Err(AuthError::InvalidUserData(UserDataError::SignatureMismatch))
+            ));
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! 微信公众号网页授权
+//! https://developers.weixin.qq.com/doc/offiaccount/OA_Web_Apps/Wechat_webpage_authorization.html
+//!
+//! 只能在微信内置浏览器中使用，扫码登录使用[`crate::wechat_open`]。
+//! `user_id`为openid，scope为`snsapi_base`时不调用用户信息接口，只有openid
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{
+    auth_server_builder, utils, wechat, AuthAction, AuthConfig, AuthUrlProvider, AuthUser,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub use crate::wechat::{
+    AuthCallback, ErrorResponse, GetTokenRequest, GetUserInfoRequest, RefreshTokenRequest,
+    TokenResponse, UserInfoResponse,
+};
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+}
+
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub refresh_token_url: String,
+    pub user_info_url: String,
+    /// 校验accessToken是否有效
+    pub check_token_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://open.weixin.qq.com/connect/oauth2/authorize".to_string(),
+            access_token_url: "https://api.weixin.qq.com/sns/oauth2/access_token".to_string(),
+            refresh_token_url: "https://api.weixin.qq.com/sns/oauth2/refresh_token".to_string(),
+            user_info_url: "https://api.weixin.qq.com/sns/userinfo".to_string(),
+            check_token_url: "https://api.weixin.qq.com/sns/auth".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 用户信息中国家、省份和城市的语言，如`zh_CN`、`zh_TW`和`en`，为空时使用平台默认的`zh_CN`
+    pub lang: Option<String>,
+}
+
+impl AuthConfigBuilder {
+    /// 设置用户信息的语言
+    pub fn lang<S: Into<String>>(mut self, lang: S) -> Self {
+        self.options.get_or_insert_with(Options::default).lang = Some(lang.into());
+        self
+    }
+}
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetTokenRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    /// 参数顺序不能改变，且必须带`#wechat_redirect`
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?{query}#wechat_redirect",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
+        ))
+    }
+
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = AuthCallback;
+    type AuthToken = TokenResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        let access_token_url = self.access_token_url(GetTokenRequest {
+            appid: self.config.client_id.to_string(),
+            secret: self.config.client_secret()?,
+            code: callback.code,
+        })?;
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// scope需要为`snsapi_userinfo`
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
+            access_token: token.access_token,
+            openid: token.openid,
+            lang: self.options.lang.clone(),
+        })?;
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let refresh_token_url = wechat::refresh_token_url(
+            &self.endpoints.refresh_token_url,
+            &self.config.client_id,
+            refresh_token.into(),
+        )?;
+        let response = self
+            .config
+            .http_client
+            .get(refresh_token_url)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "wechat_mp";
+
+    /// scope只能为`snsapi_base`或`snsapi_userinfo`中的一个，`build`时校验，默认为静默授权的`snsapi_base`
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            appid: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            response_type: "code".to_string(),
+            scope: scope
+                .as_ref()
+                .and_then(|scope| scope.first())
+                .cloned()
+                .unwrap_or_else(|| "snsapi_base".to_string()),
+            state,
+        })
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
+        let token = self.get_access_token(callback).await?;
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        if let Some(scope) = &config.scope {
+            let valid = matches!(
+                scope.as_slice(),
+                [scope] if scope == "snsapi_base" || scope == "snsapi_userinfo"
+            );
+            if !valid {
+                return Err(AuthError::InvalidConfig {
+                    field: "scope",
+                    source: "must be exactly one of snsapi_base or snsapi_userinfo".into(),
+                });
+            }
+        }
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+        })
+    }
+
+    /// 检验accessToken是否有效，无效时返回平台的错误
+    ///
+    /// https://developers.weixin.qq.com/doc/offiaccount/OA_Web_Apps/Wechat_webpage_authorization.html#4
+    pub async fn check_access_token<T: Into<String>, O: Into<String>>(
+        &self,
+        access_token: T,
+        openid: O,
+    ) -> Result<()> {
+        let query = serde_urlencoded::to_string(CheckTokenRequest {
+            access_token: Secret::new(access_token),
+            openid: openid.into(),
+        })?;
+        let response = self
+            .config
+            .http_client
+            .get(format!("{}?{query}", self.endpoints.check_token_url))
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let userinfo = utils::split_scopes(&token.scope)
+            .iter()
+            .any(|scope| scope == "snsapi_userinfo");
+        if !userinfo {
+            return Ok(AuthUser {
+                provider: Self::PROVIDER.to_string(),
+                user_id: token.openid.clone(),
+                union_id: token.unionid.clone(),
+                open_id: Some(token.openid.clone()),
+                token: Some(token.into()),
+                ..Default::default()
+            });
+        }
+        let user = self.get_user_info(token.clone()).await?;
+        Ok(wechat::auth_user(
+            Self::PROVIDER,
+            token.openid.clone(),
+            user,
+            token.into(),
+        ))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    appid: String,
+    redirect_uri: String,
+    response_type: String,
+    scope: String,
+    state: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckTokenRequest {
+    access_token: Secret,
+    openid: String,
+}
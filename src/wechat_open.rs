@@ -1,20 +1,82 @@
 //! 微信开放平台
 //! https://developers.weixin.qq.com/doc/oplatform/Website_App/WeChat_Login/Wechat_Login.html
+//!
+//! openid只在当前应用内唯一，unionid在同一开放平台账号下的网站、App、公众号和小程序之间统一，
+//! 应用未绑定开放平台账号时没有unionid。`user_id`默认使用unionid，没有unionid时使用openid，
+//! 可以通过[`Options::user_id`]改为总是使用openid。两者分别保存在[`AuthUser::open_id`]和[`AuthUser::union_id`]中
+use crate::error::Result;
+use crate::state::StateData;
 use crate::{
-    auth_server_builder, error::Result, AuthAction, AuthConfig, AuthUrlProvider, AuthUser,
+    auth_server_builder, utils, wechat, AuthAction, AuthConfig, AuthUrlProvider, AuthUser,
     GenericAuthAction,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use serde_with::{formats::CommaSeparator, serde_as, StringWithSeparator};
-use std::collections::HashMap;
+
+pub use crate::wechat::{
+    AuthCallback, ErrorResponse, GetTokenRequest, GetUserInfoRequest, RefreshTokenRequest,
+    TokenResponse, UserInfoResponse,
+};
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+}
+
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub refresh_token_url: String,
+    pub user_info_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://open.weixin.qq.com/connect/qrconnect".to_string(),
+            access_token_url: "https://api.weixin.qq.com/sns/oauth2/access_token".to_string(),
+            refresh_token_url: "https://api.weixin.qq.com/sns/oauth2/refresh_token".to_string(),
+            user_info_url: "https://api.weixin.qq.com/sns/userinfo".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 作为`user_id`的用户标识
+    pub user_id: UserIdType,
+    /// 用户信息中国家、省份和城市的语言，如`zh_CN`、`zh_TW`和`en`，为空时使用平台默认的`zh_CN`
+    pub lang: Option<String>,
 }
 
-auth_server_builder!();
+/// 作为`user_id`的用户标识
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UserIdType {
+    /// 用于网站、App和小程序之间关联账号，应用未绑定开放平台账号时使用openid
+    #[default]
+    UnionId,
+    OpenId,
+}
+
+impl AuthConfigBuilder {
+    /// 设置作为`user_id`的用户标识
+    pub fn user_id(mut self, user_id: UserIdType) -> Self {
+        self.options.get_or_insert_with(Options::default).user_id = user_id;
+        self
+    }
+
+    /// 设置用户信息的语言
+    pub fn lang<S: Into<String>>(mut self, lang: S) -> Self {
+        self.options.get_or_insert_with(Options::default).lang = Some(lang.into());
+        self
+    }
+}
 
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
@@ -23,23 +85,25 @@ impl AuthUrlProvider for AuthorizationServer {
 
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://open.weixin.qq.com/connect/qrconnect?response_type=code&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://api.weixin.qq.com/sns/oauth2/access_token?grant_type=authorization_code&{query}"
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String> {
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!("https://api.weixin.qq.com/sns/userinfo?{query}"))
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
@@ -50,69 +114,109 @@ impl AuthAction for AuthorizationServer {
     type AuthUser = UserInfoResponse;
 
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
-        let AuthConfig {
-            client_id,
-            client_secret,
-            ..
-        } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let AuthConfig { client_id, .. } = &self.config;
+        let access_token_url = self.access_token_url(GetTokenRequest {
             appid: client_id.to_string(),
-            secret: client_secret.clone().expect("client_secret is empty"),
+            secret: self.config.client_secret()?,
             code: callback.code,
         })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
-            openid: token.unionid,
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
             access_token: token.access_token,
-            ..Default::default()
+            openid: token.openid,
+            lang: self.options.lang.clone(),
         })?;
-        Ok(reqwest::get(user_info_url).await?.json().await?)
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+    }
+
+    /// https://developers.weixin.qq.com/doc/oplatform/Website_App/WeChat_Login/Authorized_Interface_Calling_UnionID.html
+    async fn refresh_token<S: Into<String> + Send>(
+        &self,
+        refresh_token: S,
+    ) -> Result<Self::AuthToken> {
+        let refresh_token_url = wechat::refresh_token_url(
+            &self.endpoints.refresh_token_url,
+            &self.config.client_id,
+            refresh_token.into(),
+        )?;
+        let response = self
+            .config
+            .http_client
+            .get(refresh_token_url)
+            .send()
+            .await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "wechat_open";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             appid: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: Some(state.into()),
-            scope: scope
-                .clone()
-                .or_else(|| {
-                    Some(vec![
-                        "snsapi_base".into(),
-                        "snsapi_login".into(),
-                        "snsapi_userinfo".into(),
-                    ])
-                })
-                .expect("scope is empty"),
+            state: Some(state),
+            scope: scope.clone().unwrap_or_else(|| {
+                vec![
+                    "snsapi_base".into(),
+                    "snsapi_login".into(),
+                    "snsapi_userinfo".into(),
+                ]
+            }),
             ..Default::default()
         })
     }
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
-        let user = self.get_user_info(token.clone()).await?;
-        Ok(AuthUser {
-            user_id: user.unionid,
-            name: user.nickname,
-            access_token: token.access_token,
-            refresh_token: token.refresh_token,
-            expires_in: token.expires_in,
-            extra: user.extra,
+        self.auth_user(token).await
+    }
+
+    async fn refresh<S: Into<String> + Send>(&self, refresh_token: S) -> Result<AuthUser> {
+        let token = self.refresh_token(refresh_token).await?;
+        self.auth_user(token).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        Ok(Self {
+            config,
+            endpoints,
+            options,
         })
     }
+
+    async fn auth_user(&self, token: TokenResponse) -> Result<AuthUser> {
+        let user = self.get_user_info(token.clone()).await?;
+        let user_id = match self.options.user_id {
+            UserIdType::UnionId => user.unionid.clone().unwrap_or_else(|| user.openid.clone()),
+            UserIdType::OpenId => user.openid.clone(),
+        };
+        Ok(wechat::auth_user(
+            Self::PROVIDER,
+            user_id,
+            user,
+            token.into(),
+        ))
+    }
 }
 
 #[serde_as]
@@ -132,49 +236,3 @@ pub enum Lang {
     En,
     Cn,
 }
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AuthCallback {
-    code: String,
-    state: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetTokenRequest {
-    appid: String,
-    secret: String,
-    code: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RefreshTokenRequest {
-    grant_type: String,
-    appid: String,
-    refresh_token: String,
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct TokenResponse {
-    pub access_token: String,
-    pub expires_in: i64,
-    pub refresh_token: String,
-    pub openid: String,
-    pub scope: String,
-    pub unionid: String,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct GetUserInfoRequest {
-    access_token: String,
-    openid: String,
-    lang: Option<String>,
-}
-
-/// https://developers.weixin.qq.com/doc/oplatform/Website_App/WeChat_Login/Authorized_Interface_Calling_UnionID.html
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct UserInfoResponse {
-    pub unionid: String,
-    pub nickname: String,
-    #[serde(flatten)]
-    pub extra: HashMap<String, Value>,
-}
@@ -0,0 +1,442 @@
+//! https://developer.work.weixin.qq.com/document/path/98151
+//! https://developer.work.weixin.qq.com/document/path/91335
+//!
+//! 企业微信的扫码登录和应用内网页授权，`client_id`为企业ID(corpid)，`client_secret`为应用的Secret。
+//! 与微信开放平台不同，企业微信没有用户级的accessToken，所有接口都使用企业的access_token，
+//! 它缓存在[`Options::access_token`]中，过期前不会重新获取。
+//! 用户的`token`为`None`，user_ticket只有应用内授权且scope为`snsapi_privateinfo`时才有
+use crate::credential::AccessTokenCache;
+use crate::error::{AuthError, Result};
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{
+    auth_server_builder, utils, AuthAction, AuthConfig, AuthUrlProvider, AuthUser, Gender,
+    GenericAuthAction,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+
+pub struct AuthorizationServer {
+    config: AuthConfig,
+    endpoints: Endpoints,
+    options: Options,
+}
+
+auth_server_builder!(options = Options);
+
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    /// 扫码登录
+    pub authorize_url: String,
+    /// 在企业微信内打开的网页授权
+    pub in_app_authorize_url: String,
+    /// 获取企业的access_token
+    pub access_token_url: String,
+    /// 通过code获取userid或openid
+    pub user_identity_url: String,
+    pub user_info_url: String,
+    pub user_detail_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://login.work.weixin.qq.com/wwlogin/sso/login".to_string(),
+            in_app_authorize_url: "https://open.weixin.qq.com/connect/oauth2/authorize".to_string(),
+            access_token_url: "https://qyapi.weixin.qq.com/cgi-bin/gettoken".to_string(),
+            user_identity_url: "https://qyapi.weixin.qq.com/cgi-bin/auth/getuserinfo".to_string(),
+            user_info_url: "https://qyapi.weixin.qq.com/cgi-bin/user/get".to_string(),
+            user_detail_url: "https://qyapi.weixin.qq.com/cgi-bin/auth/getuserdetail".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 应用的AgentId
+    pub agent_id: String,
+    /// 是否允许非企业成员登录，默认返回[`AuthError::NotMember`]。
+    /// 允许时非企业成员的`user_id`和`open_id`为openid，`tenant_id`为`None`
+    pub allow_external_users: bool,
+    /// 企业access_token的缓存，多个`AuthorizationServer`可以共享同一个缓存，不同企业应用分别缓存
+    pub access_token: AccessTokenCache,
+}
+
+impl AuthConfigBuilder {
+    /// 设置应用的AgentId
+    pub fn agent_id<S: Into<String>>(mut self, agent_id: S) -> Self {
+        self.options.get_or_insert_with(Options::default).agent_id = agent_id.into();
+        self
+    }
+
+    /// 设置是否允许非企业成员登录
+    pub fn allow_external_users(mut self, allow: bool) -> Self {
+        self.options
+            .get_or_insert_with(Options::default)
+            .allow_external_users = allow;
+        self
+    }
+}
+
+/// access_token无效或过期的错误码
+///
+/// https://developer.work.weixin.qq.com/document/path/96213
+const INVALID_ACCESS_TOKEN: [&str; 2] = ["40014", "42001"];
+
+impl AuthUrlProvider for AuthorizationServer {
+    type AuthRequest = AuthRequest;
+    type TokenRequest = GetUserIdentityRequest;
+    type UserInfoRequest = GetUserInfoRequest;
+
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!(
+            "{}?login_type=CorpApp&{query}",
+            self.endpoints.authorize_url
+        ))
+    }
+
+    /// 使用企业的access_token和code获取用户身份
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.user_identity_url))
+    }
+
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
+        let query = serde_urlencoded::to_string(request)?;
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
+    }
+}
+
+#[async_trait]
+impl AuthAction for AuthorizationServer {
+    type AuthCallback = AuthCallback;
+    type AuthToken = UserIdentityResponse;
+    type AuthUser = UserInfoResponse;
+
+    async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
+        self.with_access_token(|access_token| async {
+            let user_identity_url = self.access_token_url(GetUserIdentityRequest {
+                access_token: Secret::new(access_token),
+                code: callback.code.clone(),
+            })?;
+            let response = self
+                .config
+                .http_client
+                .get(user_identity_url)
+                .send()
+                .await?;
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+        })
+        .await
+    }
+
+    /// 非企业成员没有userid，无法获取成员信息
+    async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
+        let userid = token.userid.ok_or(AuthError::NotMember(Self::PROVIDER))?;
+        self.with_access_token(|access_token| async {
+            let user_info_url = self.user_info_url(GetUserInfoRequest {
+                access_token: Secret::new(access_token),
+                userid: userid.clone(),
+            })?;
+            let response = self.config.http_client.get(user_info_url).send().await?;
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "wecom";
+
+    /// 扫码登录
+    async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            ..
+        } = &self.config;
+        self.authorize_url(AuthRequest {
+            appid: client_id.to_string(),
+            agentid: self.options.agent_id.clone(),
+            redirect_uri: redirect_uri.to_string(),
+            state,
+        })
+    }
+
+    async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
+        let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
+        let identity = self.get_access_token(callback).await?;
+        self.auth_user(identity).await
+    }
+}
+
+impl AuthorizationServer {
+    fn new(config: AuthConfig, endpoints: Endpoints, options: Options) -> Result<Self> {
+        if options.agent_id.is_empty() {
+            return Err(AuthError::MissingConfig("agent_id"));
+        }
+        Ok(Self {
+            config,
+            endpoints,
+            options,
+        })
+    }
+
+    /// 在企业微信内打开的网页授权，scope默认为`snsapi_base`，
+    /// 需要头像、邮箱等敏感信息时使用`snsapi_privateinfo`
+    pub async fn authorize_in_app<S: Into<String>>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
+        let AuthConfig {
+            client_id,
+            redirect_uri,
+            scope,
+            ..
+        } = &self.config;
+        let query = serde_urlencoded::to_string(InAppAuthRequest {
+            appid: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: scope
+                .as_ref()
+                .and_then(|scope| scope.first())
+                .cloned()
+                .unwrap_or_else(|| "snsapi_base".to_string()),
+            state,
+            agentid: self.options.agent_id.clone(),
+        })?;
+        Ok(format!(
+            "{}?response_type=code&{query}#wechat_redirect",
+            self.endpoints.in_app_authorize_url
+        ))
+    }
+
+    /// 获取缓存的企业access_token，过期时重新获取
+    pub async fn access_token(&self) -> Result<String> {
+        self.options
+            .access_token
+            .get_or_fetch(
+                &self.endpoints.access_token_url,
+                &self.config.client_id,
+                || async {
+                    let query = serde_urlencoded::to_string(GetTokenRequest {
+                        corpid: self.config.client_id.to_string(),
+                        corpsecret: self.config.client_secret()?,
+                    })?;
+                    let response = self
+                        .config
+                        .http_client
+                        .get(format!("{}?{query}", self.endpoints.access_token_url))
+                        .send()
+                        .await?;
+                    let token: TokenResponse =
+                        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await?;
+                    Ok((token.access_token.secret().to_string(), token.expires_in))
+                },
+            )
+            .await
+    }
+
+    /// 成员的敏感信息，如头像、性别、邮箱和手机号
+    ///
+    /// https://developer.work.weixin.qq.com/document/path/95833
+    pub async fn user_detail<S: Into<String>>(&self, user_ticket: S) -> Result<UserDetailResponse> {
+        let request = GetUserDetailRequest {
+            user_ticket: Secret::new(user_ticket),
+        };
+        self.with_access_token(|access_token| async {
+            let query = serde_urlencoded::to_string([("access_token", access_token)])?;
+            let response = self
+                .config
+                .http_client
+                .post(format!("{}?{query}", self.endpoints.user_detail_url))
+                .json(&request)
+                .send()
+                .await?;
+            utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
+        })
+        .await
+    }
+
+    /// access_token被提前作废时清除缓存并重试一次
+    async fn with_access_token<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let access_token = self.access_token().await?;
+        match request(access_token).await {
+            Err(AuthError::Provider { code, .. })
+                if INVALID_ACCESS_TOKEN.contains(&code.as_str()) =>
+            {
+                self.options
+                    .access_token
+                    .invalidate(&self.endpoints.access_token_url, &self.config.client_id)
+                    .await;
+                request(self.access_token().await?).await
+            }
+            result => result,
+        }
+    }
+
+    /// 企业成员合并成员信息和敏感信息，非企业成员只有openid。
+    /// 企业微信不签发用户的accessToken，`token`为`None`，
+    /// user_ticket在登录时已用于获取敏感信息，需要时从[`UserIdentityResponse::user_ticket`]获取
+    async fn auth_user(&self, identity: UserIdentityResponse) -> Result<AuthUser> {
+        if identity.userid.is_none() {
+            if !self.options.allow_external_users {
+                return Err(AuthError::NotMember(Self::PROVIDER));
+            }
+            let openid = identity
+                .openid
+                .clone()
+                .ok_or_else(|| AuthError::InvalidResponse {
+                    provider: Self::PROVIDER,
+                    message: "neither userid nor openid is returned".to_string(),
+                })?;
+            return Ok(AuthUser {
+                provider: Self::PROVIDER.to_string(),
+                user_id: openid.clone(),
+                open_id: Some(openid),
+                extra: identity.extra,
+                ..Default::default()
+            });
+        }
+        let mut user = self.get_user_info(identity.clone()).await?;
+        if let Some(user_ticket) = &identity.user_ticket {
+            let detail = self.user_detail(user_ticket.secret()).await?;
+            user.extra.extend(detail.extra);
+        }
+        Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
+            user_id: user.userid,
+            name: user.name,
+            email: utils::get_str(&user.extra, "email")
+                .or_else(|| utils::get_str(&user.extra, "biz_mail")),
+            avatar: utils::get_str(&user.extra, "avatar"),
+            gender: match utils::get_str(&user.extra, "gender").as_deref() {
+                Some("1") => Some(Gender::Male),
+                Some("2") => Some(Gender::Female),
+                _ => None,
+            },
+            tenant_id: Some(self.config.client_id.to_string()),
+            extra: user.extra,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthRequest {
+    appid: String,
+    agentid: String,
+    redirect_uri: String,
+    state: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InAppAuthRequest {
+    appid: String,
+    redirect_uri: String,
+    scope: String,
+    state: String,
+    /// scope为`snsapi_privateinfo`时必填
+    agentid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCallback {
+    code: String,
+    state: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetTokenRequest {
+    corpid: String,
+    corpsecret: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Secret,
+    expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserIdentityRequest {
+    access_token: Secret,
+    code: String,
+}
+
+/// 企业成员返回userid，非企业成员返回openid
+///
+/// https://developer.work.weixin.qq.com/document/path/91023
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserIdentityResponse {
+    pub userid: Option<String>,
+    pub openid: Option<String>,
+    /// 应用内授权且scope为`snsapi_privateinfo`时返回，用于获取成员敏感信息
+    pub user_ticket: Option<Secret>,
+    pub expires_in: Option<i64>,
+    /// 非企业成员为客户时返回external_userid
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserInfoRequest {
+    access_token: Secret,
+    userid: String,
+}
+
+/// 2022年6月后创建的应用不再返回头像、性别、邮箱等敏感信息
+///
+/// https://developer.work.weixin.qq.com/document/path/90196
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub userid: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetUserDetailRequest {
+    user_ticket: Secret,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserDetailResponse {
+    pub userid: String,
+    /// 包括gender、avatar、email、biz_mail和mobile
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// 所有接口的http状态码都为200，errcode不为0时表示错误
+///
+/// https://developer.work.weixin.qq.com/document/path/96213
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    errcode: Option<i64>,
+    errmsg: Option<String>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match self.errcode {
+            Some(errcode) if errcode != 0 => {
+                Some((errcode.to_string(), self.errmsg.unwrap_or_default()))
+            }
+            _ => None,
+        }
+    }
+}
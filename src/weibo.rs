@@ -1,19 +1,42 @@
 //! https://open.weibo.com/wiki/授权机制说明
-use crate::{auth_server_builder, AuthUser, GenericAuthAction};
+use crate::secret::Secret;
+use crate::state::StateData;
+use crate::{auth_server_builder, utils, AuthToken, AuthUser, Gender, GenericAuthAction};
 use crate::{error::Result, AuthAction, AuthConfig, AuthUrlProvider};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use serde_with::DisplayFromStr;
 use serde_with::{formats::CommaSeparator, serde_as, StringWithSeparator};
+use serde_with::{DisplayFromStr, PickFirst};
 use std::collections::HashMap;
 
 pub struct AuthorizationServer {
     config: AuthConfig,
+    endpoints: Endpoints,
 }
 
 auth_server_builder!();
 
+/// 各接口地址，默认为官方地址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoints {
+    pub authorize_url: String,
+    pub access_token_url: String,
+    pub user_info_url: String,
+    pub revoke_url: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            authorize_url: "https://api.weibo.com/oauth2/authorize".to_string(),
+            access_token_url: "https://api.weibo.com/oauth2/access_token".to_string(),
+            user_info_url: "https://api.weibo.com/2/eps/user/info.json".to_string(),
+            revoke_url: "https://api.weibo.com/oauth2/revokeoauth2".to_string(),
+        }
+    }
+}
+
 impl AuthUrlProvider for AuthorizationServer {
     type AuthRequest = AuthRequest;
 
@@ -21,25 +44,25 @@ impl AuthUrlProvider for AuthorizationServer {
 
     type UserInfoRequest = GetUserInfoRequest;
 
-    fn authorize_url(request: Self::AuthRequest) -> Result<String> {
+    fn authorize_url(&self, request: Self::AuthRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://api.weibo.com/oauth2/authorize?response_type=code&{query}"
+            "{}?response_type=code&{query}",
+            self.endpoints.authorize_url
         ))
     }
 
-    fn access_token_url(request: Self::TokenRequest) -> Result<String> {
+    fn access_token_url(&self, request: Self::TokenRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
         Ok(format!(
-            "https://api.weibo.com/oauth2/access_token?grant_type=authorization_code&{query}"
+            "{}?grant_type=authorization_code&{query}",
+            self.endpoints.access_token_url
         ))
     }
 
-    fn user_info_url(request: Self::UserInfoRequest) -> Result<String> {
+    fn user_info_url(&self, request: Self::UserInfoRequest) -> Result<String> {
         let query = serde_urlencoded::to_string(request)?;
-        Ok(format!(
-            "https://api.weibo.com/2/eps/user/info.json?{query}"
-        ))
+        Ok(format!("{}?{query}", self.endpoints.user_info_url))
     }
 }
 
@@ -52,62 +75,85 @@ impl AuthAction for AuthorizationServer {
     async fn get_access_token(&self, callback: Self::AuthCallback) -> Result<Self::AuthToken> {
         let AuthConfig {
             client_id,
-            client_secret,
             redirect_uri,
             ..
         } = &self.config;
-        let access_token_url = Self::access_token_url(GetTokenRequest {
+        let access_token_url = self.access_token_url(GetTokenRequest {
             client_id: client_id.to_string(),
-            client_secret: client_secret.clone().expect("client_secret is empty"),
+            client_secret: self.config.client_secret()?,
             code: callback.code,
             redirect_uri: redirect_uri.to_string(),
         })?;
-        Ok(reqwest::get(access_token_url).await?.json().await?)
+        let response = self.config.http_client.get(access_token_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 
     async fn get_user_info(&self, token: Self::AuthToken) -> Result<Self::AuthUser> {
-        let user_info_url = Self::user_info_url(GetUserInfoRequest {
+        let user_info_url = self.user_info_url(GetUserInfoRequest {
             access_token: token.access_token,
             uid: token.uid,
         })?;
-        Ok(reqwest::get(user_info_url).await?.json().await?)
+        let response = self.config.http_client.get(user_info_url).send().await?;
+        utils::parse_response::<ErrorResponse, _>(Self::PROVIDER, response).await
     }
 }
 
 #[async_trait]
 impl GenericAuthAction for AuthorizationServer {
+    const PROVIDER: &'static str = "weibo";
+
     async fn authorize<S: Into<String> + Send>(&self, state: S) -> Result<String> {
+        let state = state.into();
+        self.config.save_state(&state, StateData::default()).await?;
         let AuthConfig {
             client_id,
             redirect_uri,
             scope,
             ..
         } = &self.config;
-        Self::authorize_url(AuthRequest {
+        self.authorize_url(AuthRequest {
             client_id: client_id.to_string(),
             redirect_uri: redirect_uri.to_string(),
-            state: Some(state.into()),
-            scope: scope
-                .clone()
-                .or_else(|| Some(vec!["email".into()]))
-                .expect("scope is empty"),
+            state: Some(state),
+            scope: scope.clone().unwrap_or_else(|| vec!["email".into()]),
             ..Default::default()
         })
     }
 
     async fn login<S: Into<String> + Send>(&self, callback: S) -> Result<AuthUser> {
         let callback: AuthCallback = serde_urlencoded::from_str(&callback.into())?;
+        self.config.verify_state(callback.state.as_deref()).await?;
         let token = self.get_access_token(callback).await?;
         let user = self.get_user_info(token.clone()).await?;
         Ok(AuthUser {
+            provider: Self::PROVIDER.to_string(),
             user_id: user.uid,
             name: user.nickname,
-            access_token: token.access_token,
-            refresh_token: "".to_string(),
-            expires_in: token.expires_in,
+            avatar: utils::get_str(&user.extra, "avatar_large")
+                .or_else(|| utils::get_str(&user.extra, "profile_image_url")),
+            gender: match utils::get_str(&user.extra, "gender").as_deref() {
+                Some("m") => Some(Gender::Male),
+                Some("f") => Some(Gender::Female),
+                _ => None,
+            },
+            location: utils::get_str(&user.extra, "location"),
+            token: Some(token.into()),
             extra: user.extra,
+            ..Default::default()
         })
     }
+
+    /// https://open.weibo.com/wiki/Oauth2/revokeoauth2
+    async fn revoke<S: Into<String> + Send>(&self, access_token: S) -> Result<()> {
+        let response = self
+            .config
+            .http_client
+            .post(&self.endpoints.revoke_url)
+            .form(&[("access_token", access_token.into())])
+            .send()
+            .await?;
+        utils::check_response::<ErrorResponse>(Self::PROVIDER, response).await
+    }
 }
 
 #[serde_as]
@@ -132,23 +178,33 @@ pub struct AuthCallback {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetTokenRequest {
     client_id: String,
-    client_secret: String,
+    client_secret: Secret,
     code: String,
     redirect_uri: String,
 }
 
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenResponse {
-    access_token: String,
+    access_token: Secret,
+    /// 文档中remind_in和uid为字符串，兼容数字
+    #[serde_as(as = "PickFirst<(_, DisplayFromStr)>")]
     remind_in: i64,
     expires_in: i64,
+    #[serde_as(as = "PickFirst<(_, DisplayFromStr)>")]
     uid: i64,
 }
 
+impl From<TokenResponse> for AuthToken {
+    fn from(token: TokenResponse) -> Self {
+        AuthToken::new(token.access_token).with_expires_in(token.expires_in)
+    }
+}
+
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetUserInfoRequest {
-    access_token: String,
+    access_token: Secret,
     #[serde_as(as = "DisplayFromStr")]
     uid: i64,
 }
@@ -161,3 +217,20 @@ pub struct UserInfoResponse {
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// https://open.weibo.com/wiki/Error_code
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    error: Option<String>,
+    error_code: Option<i64>,
+}
+
+impl utils::ErrorResponse for ErrorResponse {
+    fn into_error(self) -> Option<(String, String)> {
+        match (self.error_code, self.error) {
+            (Some(code), error) => Some((code.to_string(), error.unwrap_or_default())),
+            (None, Some(error)) => Some((String::new(), error)),
+            (None, None) => None,
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! 用[`MockServer`]签发各种不合法的id_token，校验OIDC、Google和Microsoft会拒绝登录
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::Algorithm;
+use just_auth::error::{AuthError, IdTokenError};
+use just_auth::state::MemoryStateStore;
+use just_auth::testing::{IdTokenFault, MockServer, MockUser, Scenario};
+use just_auth::{google, microsoft, oidc, GenericAuthAction};
+use std::sync::Arc;
+
+const REDIRECT_URI: &str = "http://localhost/callback";
+
+async fn start(fault: Option<IdTokenFault>) -> MockServer {
+    let mock = MockServer::start().await.unwrap();
+    let mut user = MockUser::default();
+    user.extra.insert("tid".into(), "mock-tenant".into());
+    user.extra.insert("oid".into(), "mock-oid".into());
+    user.extra.insert("hd".into(), "example.com".into());
+    mock.set_user(user);
+    if let Some(fault) = fault {
+        mock.set_scenario(Scenario::InvalidIdToken(fault));
+    }
+    mock
+}
+
+async fn login<T: GenericAuthAction + Sync>(mock: &MockServer, server: &T) -> AuthError {
+    let url = server.authorize("state").await.unwrap();
+    let callback = mock.callback_query(&url).await.unwrap();
+    server.login(callback).await.unwrap_err()
+}
+
+async fn oidc_error(fault: IdTokenFault) -> IdTokenError {
+    let mock = start(Some(fault)).await;
+    let server = oidc::AuthorizationServer::builder()
+        .client_id("client-id")
+        .redirect_uri(REDIRECT_URI)
+        .endpoints(mock.oidc_endpoints())
+        .state_store(Arc::new(MemoryStateStore::default()))
+        .build()
+        .unwrap();
+    match login(&mock, &server).await {
+        AuthError::InvalidIdToken(error) => error,
+        error => panic!("unexpected error: {error:?}"),
+    }
+}
+
+fn jwt_error_kind(error: IdTokenError) -> ErrorKind {
+    match error {
+        IdTokenError::Jwt(error) => error.into_kind(),
+        error => panic!("unexpected error: {error:?}"),
+    }
+}
+
+#[tokio::test]
+async fn oidc_rejects_wrong_audience() {
+    let error = oidc_error(IdTokenFault::Audience).await;
+    assert!(matches!(jwt_error_kind(error), ErrorKind::InvalidAudience));
+}
+
+#[tokio::test]
+async fn oidc_rejects_wrong_issuer() {
+    let error = oidc_error(IdTokenFault::Issuer).await;
+    assert!(matches!(jwt_error_kind(error), ErrorKind::InvalidIssuer));
+}
+
+#[tokio::test]
+async fn oidc_rejects_wrong_nonce() {
+    let error = oidc_error(IdTokenFault::Nonce).await;
+    assert!(matches!(error, IdTokenError::ClaimMismatch("nonce")));
+}
+
+#[tokio::test]
+async fn oidc_rejects_expired() {
+    let error = oidc_error(IdTokenFault::Expired).await;
+    assert!(matches!(jwt_error_kind(error), ErrorKind::ExpiredSignature));
+}
+
+#[tokio::test]
+async fn oidc_rejects_alg_none() {
+    // jsonwebtoken不认识none，解析header时就会失败
+    let error = oidc_error(IdTokenFault::Unsigned).await;
+    assert!(matches!(error, IdTokenError::Jwt(_)));
+}
+
+#[tokio::test]
+async fn oidc_rejects_hs256() {
+    let error = oidc_error(IdTokenFault::Symmetric).await;
+    assert!(matches!(
+        error,
+        IdTokenError::UnsupportedAlgorithm(Algorithm::HS256)
+    ));
+}
+
+fn google_server(mock: &MockServer, hosted_domain: &str) -> google::AuthorizationServer {
+    google::AuthorizationServer::builder()
+        .client_id("client-id")
+        .client_secret("client-secret")
+        .redirect_uri(REDIRECT_URI)
+        .endpoints(mock.google_endpoints())
+        .state_store(Arc::new(MemoryStateStore::default()))
+        .options(google::Options {
+            hosted_domain: Some(hosted_domain.into()),
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn google_hosted_domain() {
+    let mock = start(None).await;
+    let server = google_server(&mock, "example.com");
+    let url = server.authorize("state").await.unwrap();
+    let callback = mock.callback_query(&url).await.unwrap();
+    server.login(callback).await.unwrap();
+
+    let server = google_server(&mock, "another.example.com");
+    let error = login(&mock, &server).await;
+    assert!(matches!(
+        error,
+        AuthError::InvalidIdToken(IdTokenError::ClaimMismatch("hd"))
+    ));
+}
+
+fn microsoft_server(mock: &MockServer, tenant: &str) -> microsoft::AuthorizationServer {
+    microsoft::AuthorizationServer::builder()
+        .client_id("client-id")
+        .redirect_uri(REDIRECT_URI)
+        .endpoints(mock.microsoft_endpoints())
+        .state_store(Arc::new(MemoryStateStore::default()))
+        .options(microsoft::Options {
+            allowed_tenants: vec![tenant.into()],
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn microsoft_allowed_tenants() {
+    let mock = start(None).await;
+    let server = microsoft_server(&mock, "MOCK-TENANT");
+    let url = server.authorize("state").await.unwrap();
+    let callback = mock.callback_query(&url).await.unwrap();
+    server.login(callback).await.unwrap();
+
+    let server = microsoft_server(&mock, "another-tenant");
+    let error = login(&mock, &server).await;
+    assert!(matches!(
+        error,
+        AuthError::InvalidIdToken(IdTokenError::ClaimMismatch("tid"))
+    ));
+}
+
+/// 刷新时没有返回id_token无法校验租户，不能绕过租户限制
+#[tokio::test]
+async fn microsoft_refresh_requires_id_token() {
+    let mock = start(None).await;
+    let server = microsoft_server(&mock, "mock-tenant");
+    let url = server.authorize("state").await.unwrap();
+    let callback = mock.callback_query(&url).await.unwrap();
+    let user = server.login(callback).await.unwrap();
+    let refresh_token = user.token.unwrap().refresh_token.unwrap();
+
+    mock.set_scenario(Scenario::InvalidIdToken(IdTokenFault::Missing));
+    let error = server.refresh(refresh_token.secret()).await.unwrap_err();
+    assert!(matches!(
+        error,
+        AuthError::InvalidIdToken(IdTokenError::Missing)
+    ));
+}